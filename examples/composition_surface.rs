@@ -0,0 +1,42 @@
+use wgpu_test::{Result, WindowBuilder};
+use windows::Win32::{System::Com::*, UI::HiDpi::*};
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    // Composition surfaces are shared with wgpu's DX12 device.
+    let mut window = WindowBuilder::new()
+        .title("Composition surface")
+        .size(400, 300)
+        .backends(wgpu::Backends::DX12)
+        .build()?;
+
+    // A translucent green rectangle over the swapchain, rendered without one.
+    let layer = window.add_composition_surface_layer(100.0, 75.0, 200, 150)?;
+    window.on_render_layer(layer, |state, view| {
+        let mut encoder = state.device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.,
+                        g: 0.5,
+                        b: 0.,
+                        a: 0.5,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        state.queue.submit([encoder.finish()]);
+    });
+
+    window.run()
+}
@@ -0,0 +1,18 @@
+use wgpu_test::{LoopMode, Result, WindowBuilder};
+use windows::Win32::{System::Com::*, UI::HiDpi::*};
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    // Each frame is written by a compute shader, then drawn through the DComp visual.
+    let mut window = WindowBuilder::new()
+        .title("Compute")
+        .loop_mode(LoopMode::Poll)
+        .build()?;
+    window.set_compute(true)?;
+
+    window.run()
+}
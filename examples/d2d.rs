@@ -0,0 +1,107 @@
+use wgpu_test::{Result, WindowBuilder};
+use windows::{
+    core::*,
+    Win32::{
+        Graphics::{
+            Direct2D::{Common::*, *},
+            DirectWrite::*,
+        },
+        System::Com::*,
+        UI::HiDpi::*,
+    },
+};
+
+const TEXT: &str = "Direct2D over wgpu";
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    let mut window = WindowBuilder::new()
+        .title("Direct2D")
+        .size(400, 300)
+        .build()?;
+    window.set_draw_triangle(true);
+
+    // DirectWrite objects don't depend on the device, unlike brushes, which are created from the
+    // context of each frame so they survive device loss.
+    let text_format = unsafe {
+        let factory: IDWriteFactory = DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED)?;
+        factory.CreateTextFormat(
+            w!("Segoe UI"),
+            None,
+            DWRITE_FONT_WEIGHT_SEMI_BOLD,
+            DWRITE_FONT_STYLE_NORMAL,
+            DWRITE_FONT_STRETCH_NORMAL,
+            24.0,
+            w!(""),
+        )?
+    };
+
+    // A translucent banner over the top of the triangle.
+    let layer = window.add_d2d_layer(20.0, 20.0, 360, 80)?;
+    window.on_draw_layer(layer, move |context| unsafe {
+        let Ok(background) = context.CreateSolidColorBrush(
+            &D2D1_COLOR_F {
+                r: 1.,
+                g: 1.,
+                b: 1.,
+                a: 0.6,
+            },
+            None,
+        ) else {
+            return;
+        };
+        let Ok(foreground) = context.CreateSolidColorBrush(
+            &D2D1_COLOR_F {
+                r: 0.1,
+                g: 0.1,
+                b: 0.1,
+                a: 1.,
+            },
+            None,
+        ) else {
+            return;
+        };
+
+        context.FillRoundedRectangle(
+            &D2D1_ROUNDED_RECT {
+                rect: D2D_RECT_F {
+                    left: 0.,
+                    top: 0.,
+                    right: 360.,
+                    bottom: 80.,
+                },
+                radiusX: 12.,
+                radiusY: 12.,
+            },
+            &background,
+        );
+        context.FillEllipse(
+            &D2D1_ELLIPSE {
+                point: D2D_POINT_2F { x: 40., y: 40. },
+                radiusX: 20.,
+                radiusY: 20.,
+            },
+            &foreground,
+        );
+        let text: Vec<u16> = TEXT.encode_utf16().collect();
+        context.DrawText(
+            &text,
+            &text_format,
+            &D2D_RECT_F {
+                left: 76.,
+                top: 24.,
+                right: 360.,
+                bottom: 80.,
+            },
+            &foreground,
+            D2D1_DRAW_TEXT_OPTIONS_NONE,
+            DWRITE_MEASURING_MODE_NATURAL,
+        );
+    });
+
+    window.run()
+}
@@ -0,0 +1,66 @@
+use std::{cell::Cell, rc::Rc};
+
+use wgpu_test::{Result, WindowBuilder};
+use windows::{
+    Foundation::Numerics::Matrix4x4,
+    Win32::{
+        System::Com::*,
+        UI::{HiDpi::*, Input::KeyboardAndMouse::*},
+    },
+};
+
+/// How far the viewer is from the window, in pixels, for the perspective.
+const DEPTH: f32 = 1000.0;
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    let mut window = WindowBuilder::new()
+        .title("Flip 3D (Left/Right: rotate, B: backface visibility)")
+        .build()?;
+
+    // The surface size, which is the client size, to rotate around the center.
+    let size = Rc::new(Cell::new((0, 0)));
+    window.on_render({
+        let size = size.clone();
+        move |state, view| {
+            size.set((state.surface_config.width, state.surface_config.height));
+            state.draw(view);
+        }
+    });
+
+    let mut degrees = 0.0;
+    window.on_key(move |window, event| {
+        if !event.pressed {
+            return;
+        }
+        match event.key {
+            VK_LEFT | VK_RIGHT => {
+                degrees += if event.key == VK_LEFT { -15.0 } else { 15.0 };
+                let (width, height) = size.get();
+                let (x, y) = (width as f32 / 2.0, height as f32 / 2.0);
+                let transform = Matrix4x4::translation(-x, -y, 0.0)
+                    * Matrix4x4::rotation_y(degrees)
+                    * Matrix4x4::perspective_projection(DEPTH)
+                    * Matrix4x4::translation(x, y, 0.0);
+                if let Err(error) = window.set_transform_3d(Some(transform)) {
+                    eprintln!("failed to set 3D transform: {error}");
+                }
+            }
+            // Hidden, the window turns empty past 90 degrees.
+            VK_B if !event.repeat => {
+                let visible = !window.is_backface_visible();
+                if let Err(error) = window.set_backface_visible(visible) {
+                    eprintln!("failed to change backface visibility: {error}");
+                }
+            }
+            VK_ESCAPE => window.close(),
+            _ => {}
+        }
+    });
+
+    window.run()
+}
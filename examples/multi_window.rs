@@ -0,0 +1,28 @@
+use wgpu_test::{run_event_loop, Result, SharedDevice, SurfaceStateDescriptor, WindowBuilder};
+use windows::Win32::{System::Com::*, UI::HiDpi::*};
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    // Both windows render with the same D3D11 and wgpu devices.
+    let shared = SharedDevice::new(wgpu::Backends::DX12, &SurfaceStateDescriptor::default())?;
+
+    let mut first = WindowBuilder::new()
+        .title("First window")
+        .size(400, 300)
+        .position(100, 100)
+        .shared_device(&shared)
+        .build()?;
+    let mut second = WindowBuilder::new()
+        .title("Second window")
+        .size(400, 300)
+        .position(550, 100)
+        .shared_device(&shared)
+        .build()?;
+    second.set_draw_triangle(true);
+
+    run_event_loop(&mut [&mut first, &mut second])
+}
@@ -0,0 +1,50 @@
+use wgpu_test::{Result, WindowBuilder};
+use windows::Win32::{
+    System::Com::*,
+    UI::{HiDpi::*, Input::KeyboardAndMouse::*},
+};
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    let mut window = WindowBuilder::new()
+        .title("Overlay (T: always on top, C: click-through, A: transparency)")
+        .size(400, 300)
+        .decorations(false)
+        .build()?;
+    window.set_topmost(true)?;
+    // Without a title bar, the top of the window moves it.
+    window.set_drag_region_height(32);
+
+    window.on_key(|window, event| {
+        if !event.pressed || event.repeat {
+            return;
+        }
+        match event.key {
+            VK_T => {
+                let topmost = !window.is_topmost();
+                if let Err(error) = window.set_topmost(topmost) {
+                    eprintln!("failed to change topmost: {error}");
+                }
+            }
+            // Clicks pass through, but the focused window still gets keys.
+            VK_C => {
+                let click_through = !window.is_click_through();
+                if let Err(error) = window.set_click_through(click_through) {
+                    eprintln!("failed to change click-through: {error}");
+                }
+            }
+            VK_A => {
+                let transparent = !window.is_transparent();
+                window.set_transparent(transparent);
+            }
+            VK_ESCAPE => window.close(),
+            _ => {}
+        }
+    });
+
+    window.run()
+}
@@ -0,0 +1,137 @@
+use std::time::Instant;
+
+use wgpu_test::{Result, SurfaceState, WindowBuilder};
+use windows::Win32::{System::Com::*, UI::HiDpi::*};
+
+const SHADER: &str = r#"
+@group(0) @binding(0) var<uniform> angle: f32;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(index) - 1) * 0.5;
+    let y = f32(i32(index & 1u) * 2 - 1) * 0.5;
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec4<f32>(x * c - y * s, x * s + y * c, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+/// Resources created from the window's device, recreated when the device changes.
+struct Triangle {
+    device: wgpu::Device,
+    pipeline: wgpu::RenderPipeline,
+    angle: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Triangle {
+    fn new(state: &SurfaceState) -> Self {
+        let device = &state.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rotating triangle"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("rotating triangle"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(state.view_format.into())],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let angle = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("angle"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("angle"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: angle.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            device: device.clone(),
+            pipeline,
+            angle,
+            bind_group,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    let mut window = WindowBuilder::new().title("Rotating triangle").build()?;
+
+    let start = Instant::now();
+    let mut triangle: Option<Triangle> = None;
+    window.on_render(move |state, view| {
+        let triangle = match &mut triangle {
+            Some(triangle) if triangle.device == state.device => triangle,
+            triangle => triangle.insert(Triangle::new(state)),
+        };
+
+        let angle = start.elapsed().as_secs_f32();
+        state
+            .queue
+            .write_buffer(&triangle.angle, 0, &angle.to_ne_bytes());
+
+        let mut encoder = state.device.create_command_encoder(&Default::default());
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 1.,
+                        g: 0.,
+                        b: 0.,
+                        a: 0.5,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderpass.set_pipeline(&triangle.pipeline);
+        renderpass.set_bind_group(0, &triangle.bind_group, &[]);
+        renderpass.draw(0..3, 0..1);
+        drop(renderpass);
+
+        state.queue.submit([encoder.finish()]);
+    });
+
+    window.run_vsynced()
+}
@@ -0,0 +1,21 @@
+use wgpu_test::{LoopMode, Result, WindowBuilder};
+use windows::Win32::{System::Com::*, UI::HiDpi::*};
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    // Edit the shader while the example runs, it is reloaded when saved.
+    let mut window = WindowBuilder::new()
+        .title("Shadertoy (drag: spotlight)")
+        .shadertoy(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/shadertoy.wgsl"
+        ))
+        .loop_mode(LoopMode::Poll)
+        .build()?;
+
+    window.run()
+}
@@ -0,0 +1,10 @@
+// Drawn with the vertex shader and inputs of `SurfaceState::new_shadertoy`.
+fn mainImage(fragCoord: vec2<f32>) -> vec4<f32> {
+    let uv = fragCoord / iResolution.xy;
+    let color = 0.5 + 0.5 * cos(iTime + uv.xyx + vec3<f32>(0.0, 2.0, 4.0));
+
+    // Only shows around the mouse while dragging, the rest reveals the content beneath.
+    let to_mouse = length(fragCoord - iMouse.xy) / iResolution.y;
+    let alpha = select(1.0, 1.0 - smoothstep(0.1, 0.5, to_mouse), iMouse.z > 0.0);
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,110 @@
+use std::{cell::Cell, rc::Rc};
+
+use wgpu_test::{MouseButton, MouseButtons, MouseEvent, Result, WindowBuilder};
+use windows::Win32::{
+    Foundation::RECT,
+    System::Com::*,
+    UI::{HiDpi::*, Input::KeyboardAndMouse::*},
+};
+
+/// Edge length of the squares stamped into the surface, in pixels.
+const STAMP: i32 = 32;
+
+fn main() -> Result<()> {
+    unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).ok()?;
+        SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2)?;
+    }
+
+    // Virtual surfaces are shared with wgpu's DX12 device like composition surfaces.
+    let mut window = WindowBuilder::new()
+        .title("Virtual surface (drag: draw, right click: trim, G: grow)")
+        .size(600, 400)
+        .backends(wgpu::Backends::DX12)
+        .build()?;
+
+    // Starts smaller than the window, only the stamps within it show.
+    let mut size = (300, 200);
+    let layer = window.add_virtual_surface_layer(0.0, 0.0, size.0, size.1)?;
+
+    // Each stamp clears the whole texture to the next color, but only its square is copied.
+    let stamps = Rc::new(Cell::new(0u32));
+    window.on_render_layer(layer, {
+        let stamps = stamps.clone();
+        move |state, view| {
+            let hue = stamps.get() as f64 * 0.1;
+            let mut encoder = state.device.create_command_encoder(&Default::default());
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.5 + 0.5 * hue.cos(),
+                            g: 0.5 + 0.5 * (hue + 2.0).cos(),
+                            b: 0.5 + 0.5 * (hue + 4.0).cos(),
+                            a: 1.,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            state.queue.submit([encoder.finish()]);
+        }
+    });
+
+    window.on_mouse(move |window, event| match event {
+        MouseEvent::Pressed {
+            button: MouseButton::Left,
+            position,
+            ..
+        }
+        | MouseEvent::Moved {
+            position,
+            buttons: MouseButtons { left: true, .. },
+        } => {
+            stamps.set(stamps.get() + 1);
+            let (x, y) = (position.0 - STAMP / 2, position.1 - STAMP / 2);
+            let stamp = RECT {
+                left: x,
+                top: y,
+                right: x + STAMP,
+                bottom: y + STAMP,
+            };
+            if let Err(error) = window.present_dirty(layer, &[stamp]) {
+                eprintln!("failed to draw: {error}");
+            }
+        }
+        // Releases every tile, the surface shows nothing until drawn again.
+        MouseEvent::Pressed {
+            button: MouseButton::Right,
+            ..
+        } => {
+            if let Err(error) = window.trim_virtual_surface(layer, &[]) {
+                eprintln!("failed to trim: {error}");
+            }
+        }
+        _ => {}
+    });
+
+    window.on_key(move |window, event| {
+        if !event.pressed || event.repeat {
+            return;
+        }
+        match event.key {
+            // The stamps drawn so far are kept.
+            VK_G => {
+                size = (size.0 * 2, size.1 * 2);
+                if let Err(error) = window.resize_virtual_surface(layer, size.0, size.1) {
+                    eprintln!("failed to resize: {error}");
+                }
+            }
+            VK_ESCAPE => window.close(),
+            _ => {}
+        }
+    });
+
+    window.run()
+}
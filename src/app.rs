@@ -0,0 +1,110 @@
+use std::{cell::RefCell, rc::Rc, time::Instant};
+
+use wgpu::TextureView;
+
+use crate::{
+    window::close_on_escape, KeyEvent, MouseEvent, MouseMotion, Result, ScrollEvent, SurfaceState,
+    Window,
+};
+
+/// An application driven by [`Window::run_app`], which calls it from the window's message loop
+/// and paint handler.
+///
+/// Every method has a default, the defaults together render like [`Window::run`].
+pub trait App {
+    /// Called before the first frame rendered to `state`, and again whenever the surface is
+    /// recreated with a new device after device loss, so device resources can be recreated.
+    fn init(&mut self, state: &SurfaceState) {
+        let _ = state;
+    }
+
+    /// Called before each frame with the seconds since the previous one, zero for the first.
+    fn update(&mut self, dt: f32) {
+        let _ = dt;
+    }
+
+    /// Encodes and submits commands drawing a frame to `view`, by default
+    /// [`SurfaceState::draw`].
+    fn render(&mut self, state: &SurfaceState, view: &TextureView) {
+        state.draw(view);
+    }
+
+    /// Called for mouse input in the client area.
+    fn mouse(&mut self, window: &mut Window, event: MouseEvent) {
+        let _ = (window, event);
+    }
+
+    /// Called for relative mouse motion from raw input.
+    fn mouse_motion(&mut self, window: &mut Window, motion: MouseMotion) {
+        let _ = (window, motion);
+    }
+
+    /// Called for mouse wheel rotation.
+    fn scroll(&mut self, window: &mut Window, event: ScrollEvent) {
+        let _ = (window, event);
+    }
+
+    /// Called for each character typed, see [`Window::on_char`].
+    fn char(&mut self, window: &mut Window, char: char) {
+        let _ = (window, char);
+    }
+
+    /// Called for key presses and releases, by default closes the window on Escape.
+    fn key(&mut self, window: &mut Window, event: KeyEvent) {
+        close_on_escape(window, event);
+    }
+}
+
+/// The [`App`] rendering the window's default frame, with every method left to its default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultApp;
+
+impl App for DefaultApp {}
+
+impl Window {
+    /// Installs `app` as the window's render and input handlers, then creates the window and
+    /// pumps messages until it is destroyed, like [`Window::run`].
+    pub fn run_app(&mut self, app: impl App + 'static) -> Result<()> {
+        let app = Rc::new(RefCell::new(app));
+
+        let mut device: Option<wgpu::Device> = None;
+        let mut last_frame: Option<Instant> = None;
+        self.on_render({
+            let app = app.clone();
+            move |state, view| {
+                let mut app = app.borrow_mut();
+                if device.as_ref() != Some(&state.device) {
+                    device = Some(state.device.clone());
+                    app.init(state);
+                }
+
+                let now = Instant::now();
+                let dt = last_frame.map_or(0.0, |last| (now - last).as_secs_f32());
+                last_frame = Some(now);
+
+                app.update(dt);
+                app.render(state, view);
+            }
+        });
+
+        self.on_mouse({
+            let app = app.clone();
+            move |window, event| app.borrow_mut().mouse(window, event)
+        });
+        self.on_mouse_motion({
+            let app = app.clone();
+            move |window, motion| app.borrow_mut().mouse_motion(window, motion)
+        });
+        self.on_scroll({
+            let app = app.clone();
+            move |window, event| app.borrow_mut().scroll(window, event)
+        });
+        self.on_char({
+            let app = app.clone();
+            move |window, char| app.borrow_mut().char(window, char)
+        });
+        self.on_key(move |window, event| app.borrow_mut().key(window, event));
+
+        self.run()
+    }
+}
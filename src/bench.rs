@@ -0,0 +1,122 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use log::info;
+use windows::Win32::UI::WindowsAndMessaging::PostQuitMessage;
+
+use crate::{LoopMode, Result, Window, LOG_TARGET};
+
+/// Frame times measured by [`Window::run_bench`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchReport {
+    /// The time between consecutive frames, in the order they were rendered.
+    pub frame_times: Vec<Duration>,
+    /// From the start of the first measured frame until the GPU finished the last one.
+    pub total: Duration,
+}
+
+impl BenchReport {
+    pub fn frames(&self) -> usize {
+        self.frame_times.len()
+    }
+
+    pub fn average(&self) -> Duration {
+        self.total / self.frames().max(1) as u32
+    }
+
+    pub fn fps(&self) -> f64 {
+        if self.total.is_zero() {
+            return 0.0;
+        }
+        self.frames() as f64 / self.total.as_secs_f64()
+    }
+
+    /// The frame time that `percentile` percent of frames were as fast as or faster than, by
+    /// the nearest rank.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let mut sorted = self.frame_times.clone();
+        sorted.sort_unstable();
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted
+            .get(rank.clamp(1, sorted.len().max(1)) - 1)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} frames in {:.3} s, {:.1} FPS, frame time avg {:.3} ms, p50 {:.3} ms, p95 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+            self.frames(),
+            self.total.as_secs_f64(),
+            self.fps(),
+            ms(self.average()),
+            ms(self.percentile(50.0)),
+            ms(self.percentile(95.0)),
+            ms(self.percentile(99.0)),
+            ms(self.percentile(100.0)),
+        )
+    }
+}
+
+impl Window {
+    /// Creates the window and renders `frames` frames as fast as possible with the render
+    /// callback, then stops pumping messages and returns the measured frame times.
+    ///
+    /// The surface is created with [`wgpu::PresentMode::Immediate`], falling back to
+    /// [`wgpu::PresentMode::Fifo`] where unsupported, and frames are rendered in
+    /// [`LoopMode::Poll`] even while the window is inactive. The first frame, which creates the
+    /// devices, isn't measured.
+    pub fn run_bench(&mut self, frames: u32) -> Result<BenchReport> {
+        self.surface_descriptor = self
+            .surface_descriptor
+            .clone()
+            .present_mode(wgpu::PresentMode::Immediate);
+        self.set_loop_mode(LoopMode::Poll);
+        self.set_pause_when_inactive(false);
+
+        // The start of each measured frame, then the end of the last one.
+        let timestamps = Rc::new(RefCell::new(Vec::with_capacity(frames as usize + 1)));
+        let mut rendered = 0;
+        let mut render = std::mem::replace(&mut self.render_handler, Box::new(|_, _| {}));
+        self.render_handler = Box::new({
+            let timestamps = timestamps.clone();
+            move |state, view| {
+                if rendered > frames {
+                    return;
+                }
+                let mut timestamps = timestamps.borrow_mut();
+                if rendered > 0 {
+                    timestamps.push(Instant::now());
+                }
+                render(state, view);
+                rendered += 1;
+
+                if rendered > frames {
+                    state.poll();
+                    timestamps.push(Instant::now());
+                    unsafe { PostQuitMessage(0) };
+                }
+            }
+        });
+
+        self.run()?;
+
+        let timestamps = timestamps.take();
+        let frame_times: Vec<Duration> = timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+        let total = match (timestamps.first(), timestamps.last()) {
+            (Some(&first), Some(&last)) => last - first,
+            _ => Duration::ZERO,
+        };
+        let report = BenchReport { frame_times, total };
+        info!(target: LOG_TARGET, "bench: {report}");
+        Ok(report)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use wgpu::TextureFormat;
+
+use crate::{AlphaMask, SurfaceState};
+
+/// An error capturing a frame with [`SurfaceState::capture_png`].
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8-bit RGBA and BGRA frames can be read back.
+    UnsupportedFormat(TextureFormat),
+    /// Mapping the readback buffer failed.
+    Map(wgpu::BufferAsyncError),
+    /// The PNG file could not be created.
+    Io { path: PathBuf, source: io::Error },
+    /// Encoding the PNG failed.
+    Encoding(png::EncodingError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "can't capture {format:?} frames as PNG")
+            }
+            CaptureError::Map(error) => write!(f, "failed to map the readback buffer: {error}"),
+            CaptureError::Io { path, source } => {
+                write!(f, "failed to create {}: {source}", path.display())
+            }
+            CaptureError::Encoding(error) => write!(f, "failed to encode PNG: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::UnsupportedFormat(_) => None,
+            CaptureError::Map(error) => Some(error),
+            CaptureError::Io { source, .. } => Some(source),
+            CaptureError::Encoding(error) => Some(error),
+        }
+    }
+}
+
+impl SurfaceState {
+    /// Renders a frame with [`SurfaceState::draw`] into an offscreen texture the size of the
+    /// surface and writes it to `path` as PNG.
+    ///
+    /// A composition swapchain can't be read back, so this shows what wgpu produced independently
+    /// of DComp. Pixels are written as rendered, premultiplied if the alpha mode is.
+    pub fn capture_png(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        self.capture_png_with(path, |state, view| state.draw(view))
+    }
+
+    /// Like [`SurfaceState::capture_png`], but lets `render` encode and submit the frame, e.g. with
+    /// the same code as [`crate::Window::on_render`].
+    pub fn capture_png_with(
+        &self,
+        path: impl AsRef<Path>,
+        render: impl FnOnce(&SurfaceState, &wgpu::TextureView),
+    ) -> Result<(), CaptureError> {
+        let pixels = self.read_back_with(render)?;
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|source| CaptureError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        if self.view_format.is_srgb() {
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        }
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(CaptureError::Encoding)
+    }
+
+    /// Renders a frame with `render` into an offscreen texture like
+    /// [`SurfaceState::capture_png_with`] and returns the alpha of its pixels, for
+    /// [`crate::Window::set_alpha_hit_test`].
+    pub fn read_alpha_mask_with(
+        &self,
+        render: impl FnOnce(&SurfaceState, &wgpu::TextureView),
+    ) -> Result<AlphaMask, CaptureError> {
+        let pixels = self.read_back_with(render)?;
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        Ok(AlphaMask::from_rgba(width, height, &pixels))
+    }
+
+    /// Renders a frame with `render` into an offscreen texture the size of the surface and
+    /// returns its pixels in RGBA order, row by row.
+    fn read_back_with(
+        &self,
+        render: impl FnOnce(&SurfaceState, &wgpu::TextureView),
+    ) -> Result<Vec<u8>, CaptureError> {
+        let format = self.view_format;
+        let swap_red_blue = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(CaptureError::UnsupportedFormat(format)),
+        };
+
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("capture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        self.write_uniforms();
+        render(self, &texture.create_view(&Default::default()));
+
+        // Buffer copies need rows aligned to 256 bytes, the padding is removed when encoding.
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("capture"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.poll();
+        receiver
+            .recv()
+            .expect("map callback dropped")
+            .map_err(CaptureError::Map)?;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+
+        if swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(pixels)
+    }
+}
@@ -0,0 +1,395 @@
+use std::cell::Cell;
+
+use wgpu::{hal, TextureFormat};
+use windows::{
+    core::{Interface, PCWSTR},
+    Win32::{
+        Foundation::*,
+        Graphics::{
+            Direct3D11::*,
+            Direct3D12::ID3D12Resource,
+            DirectComposition::*,
+            Dxgi::{Common::*, *},
+        },
+    },
+};
+
+use crate::{Error, Result};
+
+/// The format of the texture wgpu renders to. Its D3D resource is typeless, so it can also be
+/// viewed as sRGB.
+const FORMAT: TextureFormat = TextureFormat::Bgra8Unorm;
+
+/// Renders with wgpu into an `IDCompositionSurface`, the non-swapchain way of giving a DComp
+/// visual content.
+///
+/// wgpu can't render to the texture `BeginDraw` returns directly: it belongs to DComp's D3D11
+/// device and isn't shareable. Instead wgpu renders to a texture created by D3D11 with a shared
+/// handle and opened by wgpu's DX12 device, which is copied into the surface on the D3D11 device
+/// after each frame.
+///
+/// Created with [`CompositionSurface::new_virtual`], the surface is an
+/// `IDCompositionVirtualSurface` instead: DComp only allocates the tiles that were drawn to, it
+/// can be resized without losing its content and parts of it can be released with
+/// [`CompositionSurface::trim`].
+pub struct CompositionSurface {
+    surface: IDCompositionSurface,
+    /// The same surface, if it is virtual.
+    virtual_surface: Option<IDCompositionVirtualSurface>,
+    /// The D3D11 side of `texture`.
+    shared: ID3D11Texture2D,
+    texture: wgpu::Texture,
+    context: ID3D11DeviceContext,
+    /// Signaled once the copy into the surface has finished on the D3D11 device.
+    copied: ID3D11Query,
+    /// Whether the surface was drawn to, DComp requires the first update to cover all of it.
+    drawn: Cell<bool>,
+}
+
+impl CompositionSurface {
+    /// Creates a `width` x `height` surface from `desktop` and a texture shared between
+    /// `device_3d` and `device`, which must be a DX12 device on the same adapter.
+    ///
+    /// Set [`CompositionSurface::surface`] as the content of a visual to show it.
+    pub fn new(
+        desktop: &IDCompositionDesktopDevice,
+        device_3d: &ID3D11Device,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let (width, height) = (width.max(1), height.max(1));
+        unsafe {
+            let surface = desktop
+                .CreateSurface(
+                    width,
+                    height,
+                    DXGI_FORMAT_B8G8R8A8_UNORM,
+                    DXGI_ALPHA_MODE_PREMULTIPLIED,
+                )
+                .map_err(Error::DComp)?;
+            Self::with_surface(surface, None, device_3d, device, width, height)
+        }
+    }
+
+    /// Like [`CompositionSurface::new`], but creates a virtual surface, which starts out empty.
+    /// Only the tiles covered by the rectangles given to
+    /// [`CompositionSurface::render_dirty_with`] are allocated and shown, so the first update
+    /// doesn't have to cover the whole surface.
+    ///
+    /// The texture wgpu renders to still has the size of the surface, which is limited by the
+    /// device's maximum texture size.
+    pub fn new_virtual(
+        desktop: &IDCompositionDesktopDevice,
+        device_3d: &ID3D11Device,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let (width, height) = (width.max(1), height.max(1));
+        unsafe {
+            let virtual_surface = desktop
+                .CreateVirtualSurface(
+                    width,
+                    height,
+                    DXGI_FORMAT_B8G8R8A8_UNORM,
+                    DXGI_ALPHA_MODE_PREMULTIPLIED,
+                )
+                .map_err(Error::DComp)?;
+            let surface = virtual_surface.cast().map_err(Error::DComp)?;
+            Self::with_surface(
+                surface,
+                Some(virtual_surface),
+                device_3d,
+                device,
+                width,
+                height,
+            )
+        }
+    }
+
+    unsafe fn with_surface(
+        surface: IDCompositionSurface,
+        virtual_surface: Option<IDCompositionVirtualSurface>,
+        device_3d: &ID3D11Device,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let (shared, texture) = create_shared_texture(device_3d, device, width, height)?;
+
+        let mut copied = None;
+        device_3d
+            .CreateQuery(
+                &D3D11_QUERY_DESC {
+                    Query: D3D11_QUERY_EVENT,
+                    MiscFlags: 0,
+                },
+                Some(&mut copied),
+            )
+            .map_err(Error::D3D)?;
+
+        Ok(Self {
+            drawn: Cell::new(virtual_surface.is_some()),
+            surface,
+            virtual_surface,
+            shared,
+            texture,
+            context: device_3d.GetImmediateContext().map_err(Error::D3D)?,
+            copied: copied.unwrap(),
+        })
+    }
+
+    /// The surface to set as a visual's content.
+    pub fn surface(&self) -> &IDCompositionSurface {
+        &self.surface
+    }
+
+    /// Whether the surface was created with [`CompositionSurface::new_virtual`].
+    pub fn is_virtual(&self) -> bool {
+        self.virtual_surface.is_some()
+    }
+
+    /// Resizes a virtual surface to `width` x `height` and recreates the texture wgpu renders to
+    /// at that size. DComp keeps the content of the part of the surface within both sizes, the
+    /// rest has to be drawn with [`CompositionSurface::render_dirty_with`].
+    ///
+    /// Regular surfaces can't be resized and fail with `DXGI_ERROR_UNSUPPORTED`, they have to be
+    /// recreated instead.
+    pub fn resize(
+        &mut self,
+        device_3d: &ID3D11Device,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let (width, height) = (width.max(1), height.max(1));
+        let virtual_surface = self.virtual_surface()?;
+        unsafe {
+            virtual_surface
+                .Resize(width, height)
+                .map_err(Error::DComp)?;
+            (self.shared, self.texture) = create_shared_texture(device_3d, device, width, height)?;
+        }
+        Ok(())
+    }
+
+    /// Discards the pixels of a virtual surface outside of `keep`, in pixels, releasing the tiles
+    /// no longer covered, and all of them if `keep` is empty.
+    ///
+    /// DComp only releases tiles by itself when they fall outside of the surface after a
+    /// [`CompositionSurface::resize`], so content that scrolled out of view or was removed should
+    /// be trimmed, or memory grows with every tile ever drawn. Trimmed tiles show nothing until
+    /// drawn again, and the change shows with the next commit.
+    ///
+    /// Fails with `DXGI_ERROR_UNSUPPORTED` for regular surfaces.
+    pub fn trim(&self, keep: &[RECT]) -> Result<()> {
+        let virtual_surface = self.virtual_surface()?;
+        unsafe { virtual_surface.Trim(Some(keep)).map_err(Error::DComp) }
+    }
+
+    fn virtual_surface(&self) -> Result<&IDCompositionVirtualSurface> {
+        self.virtual_surface.as_ref().ok_or_else(|| {
+            windows::core::Error::new(DXGI_ERROR_UNSUPPORTED, "the surface isn't virtual").into()
+        })
+    }
+
+    /// The texture wgpu renders to, copied into the surface by
+    /// [`CompositionSurface::render_with`].
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Lets `render` encode and submit commands drawing to an sRGB view of the texture, like
+    /// [`crate::SurfaceState::render_with`], then copies the frame into the surface.
+    ///
+    /// Without a fence shared between the two devices, this waits for `device` to finish the
+    /// frame before copying, then for D3D11 to finish the copy before returning, so the next frame
+    /// can't overwrite the texture while it is read. The surface shows the frame once `desktop`
+    /// is committed.
+    pub fn render_with(
+        &self,
+        device: &wgpu::Device,
+        render: impl FnOnce(&wgpu::TextureView),
+    ) -> Result<()> {
+        self.render_dirty_with(device, None, render)
+    }
+
+    /// Like [`CompositionSurface::render_with`], but only copies the `dirty` rectangles of the
+    /// frame into the surface, in pixels, or all of it with `None`. DComp then only updates those
+    /// parts of the surface, which saves copying and composing unchanged pixels of mostly
+    /// static content.
+    ///
+    /// The first frame of a regular surface always updates all of it, as DComp requires. Virtual
+    /// surfaces can be updated piece by piece from the start.
+    pub fn render_dirty_with(
+        &self,
+        device: &wgpu::Device,
+        dirty: Option<&[RECT]>,
+        render: impl FnOnce(&wgpu::TextureView),
+    ) -> Result<()> {
+        let view = self.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(FORMAT.add_srgb_suffix()),
+            ..Default::default()
+        });
+        render(&view);
+        device.poll(wgpu::Maintain::Wait);
+
+        unsafe {
+            match dirty.filter(|_| self.drawn.get()) {
+                Some(rects) => {
+                    let (width, height) = (self.texture.width(), self.texture.height());
+                    for rect in rects {
+                        let clamped = RECT {
+                            left: rect.left.clamp(0, width as i32),
+                            top: rect.top.clamp(0, height as i32),
+                            right: rect.right.clamp(0, width as i32),
+                            bottom: rect.bottom.clamp(0, height as i32),
+                        };
+                        if clamped.left < clamped.right && clamped.top < clamped.bottom {
+                            self.update(Some(&clamped))?;
+                        }
+                    }
+                }
+                None => self.update(None)?,
+            }
+            self.drawn.set(true);
+
+            self.context.End(&self.copied);
+            self.context.Flush();
+            // `done` is left unwritten until the query is signaled, an error means device loss.
+            let mut done = BOOL(0);
+            while !done.as_bool()
+                && self
+                    .context
+                    .GetData(
+                        &self.copied,
+                        Some(&mut done as *mut BOOL as _),
+                        size_of::<BOOL>() as u32,
+                        0,
+                    )
+                    .is_ok()
+            {
+                std::thread::yield_now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `rect` of the shared texture, or all of it, into the same part of the surface.
+    unsafe fn update(&self, rect: Option<&RECT>) -> Result<()> {
+        let mut offset = POINT::default();
+        let target: ID3D11Texture2D = self
+            .surface
+            .BeginDraw(rect.map(|rect| rect as *const _), &mut offset)
+            .map_err(Error::DComp)?;
+        let source = rect.map(|rect| D3D11_BOX {
+            left: rect.left as u32,
+            top: rect.top as u32,
+            front: 0,
+            right: rect.right as u32,
+            bottom: rect.bottom as u32,
+            back: 1,
+        });
+        // The surface may be allocated in an atlas, the updated pixels start at `offset`.
+        self.context.CopySubresourceRegion(
+            &target,
+            0,
+            offset.x as u32,
+            offset.y as u32,
+            0,
+            &self.shared,
+            0,
+            source.as_ref().map(|source| source as *const _),
+        );
+        self.surface.EndDraw().map_err(Error::DComp)
+    }
+}
+
+/// Creates a `width` x `height` texture on `device_3d` and opens it on `device`, returning both
+/// sides of it.
+unsafe fn create_shared_texture(
+    device_3d: &ID3D11Device,
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> Result<(ID3D11Texture2D, wgpu::Texture)> {
+    let mut shared = None;
+    device_3d
+        .CreateTexture2D(
+            &D3D11_TEXTURE2D_DESC {
+                Width: width,
+                Height: height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: DXGI_FORMAT_B8G8R8A8_TYPELESS,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: D3D11_BIND_RENDER_TARGET.0 as u32,
+                CPUAccessFlags: 0,
+                MiscFlags: (D3D11_RESOURCE_MISC_SHARED.0 | D3D11_RESOURCE_MISC_SHARED_NTHANDLE.0)
+                    as u32,
+            },
+            None,
+            Some(&mut shared),
+        )
+        .map_err(Error::D3D)?;
+    let shared = shared.unwrap();
+
+    let handle = shared
+        .cast::<IDXGIResource1>()
+        .and_then(|resource| {
+            resource.CreateSharedHandle(
+                None,
+                (DXGI_SHARED_RESOURCE_READ | DXGI_SHARED_RESOURCE_WRITE).0,
+                PCWSTR::null(),
+            )
+        })
+        .map_err(Error::D3D)?;
+    let resource = device.as_hal::<hal::api::Dx12, _, _>(|device| {
+        let device = device.ok_or_else(|| {
+            windows::core::Error::new(
+                DXGI_ERROR_UNSUPPORTED,
+                "composition surfaces need a DX12 wgpu device",
+            )
+        })?;
+        let mut resource: Option<ID3D12Resource> = None;
+        device
+            .raw_device()
+            .OpenSharedHandle(handle, &mut resource)
+            .map(|()| resource.unwrap())
+    });
+    let _ = CloseHandle(handle);
+    let resource = resource.map_err(Error::D3D)?;
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let hal_texture = hal::dx12::Device::texture_from_raw(
+        resource,
+        FORMAT,
+        wgpu::TextureDimension::D2,
+        size,
+        1,
+        1,
+    );
+    let texture = device.create_texture_from_hal::<hal::api::Dx12>(
+        hal_texture,
+        &wgpu::TextureDescriptor {
+            label: Some("composition surface"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[FORMAT.add_srgb_suffix()],
+        },
+    );
+    Ok((shared, texture))
+}
@@ -0,0 +1,183 @@
+use wgpu::{
+    BindGroup, BindGroupLayout, ComputePipeline, Device, Queue, SurfaceConfiguration,
+    TextureFormat, TextureView,
+};
+
+use crate::{offscreen::Composite, SurfaceStateError};
+
+/// The format of the storage texture, which core WebGPU allows as a write-only storage texture,
+/// unlike the BGRA surface formats.
+const STORAGE_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+/// Edge length of the square workgroups, in texels.
+const WORKGROUP_SIZE: u32 = 8;
+
+const COMPUTE_SHADER: &str = r#"
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+@group(1) @binding(0) var output: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    // Moving diagonal bands, written premultiplied with half opacity.
+    let uv = vec2<f32>(id.xy) / uniforms.resolution;
+    let phase = (uv.x + uv.y) * 6.0 - uniforms.time * 2.0;
+    let color = 0.5 + 0.5 * cos(phase + vec3<f32>(0.0, 2.0, 4.0));
+    let alpha = 0.5;
+    textureStore(output, id.xy, vec4<f32>(color * alpha, alpha));
+}
+"#;
+
+/// A compute shader writing each frame to a storage texture, which a [`Composite`] pass then
+/// draws to the surface texture, enabled with [`crate::SurfaceState::set_compute`].
+pub(crate) struct ComputeTarget {
+    texture: wgpu::Texture,
+    storage_layout: BindGroupLayout,
+    storage: BindGroup,
+    sampled: BindGroup,
+    pipeline: ComputePipeline,
+    composite: Composite,
+}
+
+impl ComputeTarget {
+    /// Fails if `device` can't write to storage textures of [`STORAGE_FORMAT`] from compute
+    /// shaders.
+    pub fn new(
+        device: &Device,
+        config: &SurfaceConfiguration,
+        format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+    ) -> Result<Self, SurfaceStateError> {
+        let format_features = STORAGE_FORMAT.guaranteed_format_features(device.features());
+        if !format_features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::STORAGE_BINDING)
+        {
+            return Err(SurfaceStateError::UnsupportedFeatures(
+                wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            ));
+        }
+        if device.limits().max_storage_textures_per_shader_stage == 0 {
+            return Err(SurfaceStateError::UnsupportedLimits(vec![
+                "max_storage_textures_per_shader_stage",
+            ]));
+        }
+
+        let storage_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("compute output"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: STORAGE_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("compute"),
+            source: wgpu::ShaderSource::Wgsl(COMPUTE_SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("compute"),
+            bind_group_layouts: &[uniform_layout, &storage_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("compute"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let composite = Composite::new(device, format, false);
+        let (texture, storage, sampled) =
+            create_texture(device, config, &storage_layout, &composite);
+        Ok(Self {
+            texture,
+            storage_layout,
+            storage,
+            sampled,
+            pipeline,
+            composite,
+        })
+    }
+
+    /// Recreates the storage texture at the size of `config`.
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+        (self.texture, self.storage, self.sampled) =
+            create_texture(device, config, &self.storage_layout, &self.composite);
+    }
+
+    /// Encodes and submits the dispatch covering the storage texture, then the pass drawing it
+    /// over all of `target`.
+    pub fn draw(&self, device: &Device, queue: &Queue, uniforms: &BindGroup, target: &TextureView) {
+        let mut encoder = device.create_command_encoder(&Default::default());
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, uniforms, &[]);
+        pass.set_bind_group(1, &self.storage, &[]);
+        pass.dispatch_workgroups(
+            self.texture.width().div_ceil(WORKGROUP_SIZE),
+            self.texture.height().div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+        drop(pass);
+
+        self.composite.encode(&mut encoder, &self.sampled, target);
+        queue.submit([encoder.finish()]);
+    }
+}
+
+/// Creates the storage texture, bound for writing by the compute shader and for sampling by
+/// `composite`.
+fn create_texture(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    storage_layout: &BindGroupLayout,
+    composite: &Composite,
+) -> (wgpu::Texture, BindGroup, BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("compute output"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: STORAGE_FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+    let storage = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("compute output"),
+        layout: storage_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+    let sampled = composite.bind_group(device, &view);
+    (texture, storage, sampled)
+}
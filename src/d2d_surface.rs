@@ -0,0 +1,86 @@
+use windows::{
+    Foundation::Numerics::Matrix3x2,
+    Win32::{
+        Foundation::POINT,
+        Graphics::{
+            Direct2D::{Common::*, *},
+            DirectComposition::*,
+            Dxgi::Common::*,
+        },
+    },
+};
+
+use crate::{Error, Result};
+
+/// An `IDCompositionSurface` drawn with Direct2D, showing D2D content in the same visual tree as
+/// wgpu's.
+///
+/// The DComp device must have been created from a D2D device, as [`crate::Window`]'s is, so
+/// `BeginDraw` can return a D2D device context already bound to the surface.
+pub struct D2DSurface {
+    surface: IDCompositionSurface,
+    width: u32,
+    height: u32,
+}
+
+impl D2DSurface {
+    /// Creates a `width` x `height` surface from `desktop`.
+    ///
+    /// Set [`D2DSurface::surface`] as the content of a visual to show it.
+    pub fn new(desktop: &IDCompositionDesktopDevice, width: u32, height: u32) -> Result<Self> {
+        let (width, height) = (width.max(1), height.max(1));
+        let surface = unsafe {
+            desktop.CreateSurface(
+                width,
+                height,
+                DXGI_FORMAT_B8G8R8A8_UNORM,
+                DXGI_ALPHA_MODE_PREMULTIPLIED,
+            )
+        }
+        .map_err(Error::DComp)?;
+        Ok(Self {
+            surface,
+            width,
+            height,
+        })
+    }
+
+    /// The surface to set as a visual's content.
+    pub fn surface(&self) -> &IDCompositionSurface {
+        &self.surface
+    }
+
+    /// Lets `draw` draw a frame with the device context DComp binds to the surface, in pixels of
+    /// the surface, which starts out cleared to transparent. The surface shows the frame once the
+    /// DComp device is committed.
+    ///
+    /// DComp begins and ends drawing on the context itself, `draw` must not call its `BeginDraw`
+    /// or `EndDraw`, and any error of the drawing is returned by the surface's `EndDraw`. The
+    /// surface may be allocated in an atlas shared with other surfaces, so the context is
+    /// translated and clipped to the surface's part of it, and shouldn't be used after `draw`
+    /// returns.
+    pub fn draw(&self, draw: impl FnOnce(&ID2D1DeviceContext)) -> Result<()> {
+        unsafe {
+            let mut offset = POINT::default();
+            let context: ID2D1DeviceContext = self
+                .surface
+                .BeginDraw(None, &mut offset)
+                .map_err(Error::DComp)?;
+            context.SetTransform(&Matrix3x2::translation(offset.x as f32, offset.y as f32));
+            // Clears ignore the transform but not clips, this keeps the rest of the atlas intact.
+            context.PushAxisAlignedClip(
+                &D2D_RECT_F {
+                    left: 0.,
+                    top: 0.,
+                    right: self.width as f32,
+                    bottom: self.height as f32,
+                },
+                D2D1_ANTIALIAS_MODE_ALIASED,
+            );
+            context.Clear(Some(&D2D1_COLOR_F::default()));
+            draw(&context);
+            context.PopAxisAlignedClip();
+            self.surface.EndDraw().map_err(Error::DComp)
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::{Direct2D::*, Direct3D::*, Direct3D11::*, Dxgi::*},
+    },
+};
+
+use log::{info, warn};
+
+use crate::{
+    surface::{request_adapter, request_device},
+    Error, SurfaceStateDescriptor, LOG_TARGET,
+};
+
+/// Creates a D3D11 device of `driver_type`, e.g. `D3D_DRIVER_TYPE_HARDWARE` or
+/// `D3D_DRIVER_TYPE_WARP`, with BGRA support, as required by Direct2D and DComp.
+///
+/// `debug` enables the D3D11 debug layer, which reports API misuse to the debugger output. If
+/// the layer isn't installed (it ships with the Graphics Tools optional feature), the device is
+/// created without it.
+///
+/// Like wgpu's adapter selection, a hardware device falls back to WARP if there is no GPU, e.g.
+/// on CI runners.
+pub fn create_device_3d(driver_type: D3D_DRIVER_TYPE, debug: bool) -> Result<ID3D11Device> {
+    match create_device_with_debug(driver_type, debug) {
+        Err(error)
+            if driver_type == D3D_DRIVER_TYPE_HARDWARE
+                && error.code() == DXGI_ERROR_UNSUPPORTED =>
+        {
+            warn!(
+                target: LOG_TARGET,
+                "no hardware D3D11 device, falling back to WARP"
+            );
+            create_device_with_debug(D3D_DRIVER_TYPE_WARP, debug)
+        }
+        result => result,
+    }
+}
+
+fn create_device_with_debug(driver_type: D3D_DRIVER_TYPE, debug: bool) -> Result<ID3D11Device> {
+    let flags = D3D11_CREATE_DEVICE_BGRA_SUPPORT;
+    if debug {
+        match create_device(driver_type, flags | D3D11_CREATE_DEVICE_DEBUG) {
+            Err(error) if error.code() == DXGI_ERROR_SDK_COMPONENT_MISSING => {
+                warn!(target: LOG_TARGET, "D3D11 debug layer is not installed");
+            }
+            result => return result,
+        }
+    }
+    create_device(driver_type, flags)
+}
+
+/// The feature levels requested, in order of preference. DComp only needs 9_1, higher levels are
+/// requested so interop behavior matches what the hardware can do.
+const FEATURE_LEVELS: [D3D_FEATURE_LEVEL; 4] = [
+    D3D_FEATURE_LEVEL_11_1,
+    D3D_FEATURE_LEVEL_11_0,
+    D3D_FEATURE_LEVEL_10_1,
+    D3D_FEATURE_LEVEL_10_0,
+];
+
+fn create_device(
+    driver_type: D3D_DRIVER_TYPE,
+    flags: D3D11_CREATE_DEVICE_FLAG,
+) -> Result<ID3D11Device> {
+    let create = |feature_levels: &[D3D_FEATURE_LEVEL]| {
+        let mut device = None;
+        let mut feature_level = D3D_FEATURE_LEVEL::default();
+        unsafe {
+            D3D11CreateDevice(
+                None,
+                driver_type,
+                HMODULE::default(),
+                flags,
+                Some(feature_levels),
+                D3D11_SDK_VERSION,
+                Some(&mut device),
+                Some(&mut feature_level),
+                None,
+            )
+        }
+        .map(|()| (device.unwrap(), feature_level))
+    };
+
+    // Runtimes predating D3D 11.1 reject the whole list if it contains 11_1.
+    let (device, feature_level) = match create(&FEATURE_LEVELS) {
+        Err(error) if error.code() == E_INVALIDARG => create(&FEATURE_LEVELS[1..]),
+        result => result,
+    }?;
+    info!(
+        target: LOG_TARGET,
+        "D3D11 device: {driver_type:?}, feature level {}",
+        feature_level_name(feature_level)
+    );
+    Ok(device)
+}
+
+/// Formats `level` like `11_1`.
+pub(crate) fn feature_level_name(level: D3D_FEATURE_LEVEL) -> String {
+    format!("{}_{}", level.0 >> 12, (level.0 >> 8) & 0xf)
+}
+
+/// Creates a Direct2D device on top of `device_3d`, used to create the DComp desktop device.
+pub fn create_device_2d(device_3d: &ID3D11Device) -> Result<ID2D1Device> {
+    let dxgi: IDXGIDevice3 = device_3d.cast()?;
+    unsafe { D2D1CreateDevice(&dxgi, None) }
+}
+
+/// A D3D11 device and a wgpu device shared by several windows, see
+/// [`crate::WindowBuilder::shared_device`].
+///
+/// Windows sharing a device can't recover from its loss, a new `SharedDevice` is needed then.
+#[derive(Clone, Debug)]
+pub struct SharedDevice {
+    pub device_3d: ID3D11Device,
+    /// The instance surfaces rendered with `device` must be created from.
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl SharedDevice {
+    /// Creates a hardware D3D11 device and a wgpu device with the features and limits of
+    /// `descriptor`, from an instance restricted to `backends`.
+    pub fn new(
+        backends: wgpu::Backends,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> crate::Result<Self> {
+        let device_3d = create_device_3d(D3D_DRIVER_TYPE_HARDWARE, false).map_err(Error::D3D)?;
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let adapter = pollster::block_on(request_adapter(&instance, None, descriptor))?;
+        let (device, queue) = pollster::block_on(request_device(&adapter, descriptor))?;
+
+        Ok(Self {
+            device_3d,
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::{CaptureError, SurfaceStateError};
+
+/// An error from any layer of the composition stack.
+#[derive(Debug)]
+pub enum Error {
+    /// A Win32 window management call failed.
+    Win32(windows::core::Error),
+    /// Creating the D3D11 or D2D device failed, or the device was removed.
+    D3D(windows::core::Error),
+    /// Creating or committing the DComp visual tree failed.
+    DComp(windows::core::Error),
+    /// wgpu reported an error while rendering.
+    Wgpu(wgpu::Error),
+    /// Creating the wgpu surface failed.
+    Surface(SurfaceStateError),
+    /// Acquiring a surface texture to render a frame to failed.
+    Frame(wgpu::SurfaceError),
+    /// Capturing a frame to PNG failed.
+    Capture(CaptureError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Win32(error) => write!(f, "Win32 error: {error}"),
+            Error::D3D(error) => write!(f, "D3D error: {error}"),
+            Error::DComp(error) => write!(f, "DComp error: {error}"),
+            Error::Wgpu(error) => write!(f, "wgpu error: {error}"),
+            Error::Surface(error) => write!(f, "surface error: {error}"),
+            Error::Frame(error) => write!(f, "frame error: {error}"),
+            Error::Capture(error) => write!(f, "capture error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Win32(error) | Error::D3D(error) | Error::DComp(error) => Some(error),
+            Error::Wgpu(error) => Some(error),
+            Error::Surface(error) => Some(error),
+            Error::Frame(error) => Some(error),
+            Error::Capture(error) => Some(error),
+        }
+    }
+}
+
+impl From<windows::core::Error> for Error {
+    fn from(error: windows::core::Error) -> Self {
+        Error::Win32(error)
+    }
+}
+
+impl From<wgpu::Error> for Error {
+    fn from(error: wgpu::Error) -> Self {
+        Error::Wgpu(error)
+    }
+}
+
+impl From<wgpu::SurfaceError> for Error {
+    fn from(error: wgpu::SurfaceError) -> Self {
+        Error::Frame(error)
+    }
+}
+
+impl From<CaptureError> for Error {
+    fn from(error: CaptureError) -> Self {
+        Error::Capture(error)
+    }
+}
+
+impl From<SurfaceStateError> for Error {
+    fn from(error: SurfaceStateError) -> Self {
+        Error::Surface(error)
+    }
+}
@@ -0,0 +1,146 @@
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::{Direct3D::*, Direct3D11::*, DirectComposition::*},
+        System::{Com::*, LibraryLoader::*},
+        UI::WindowsAndMessaging::*,
+    },
+};
+
+use crate::{create_device_2d, create_device_3d, Error, SurfaceState, SurfaceStateDescriptor};
+
+/// A [`SurfaceState`] on a hidden window with its own DComp stack, for tests that need a real
+/// composition surface without showing anything or running a message loop.
+pub struct HeadlessSurface {
+    /// Declared first, so it is released before the visual it was created from.
+    pub state: SurfaceState,
+    visual: IDCompositionVisual2,
+    target: IDCompositionTarget,
+    desktop: IDCompositionDesktopDevice,
+    device: ID3D11Device,
+    hwnd: HWND,
+}
+
+impl HeadlessSurface {
+    /// Creates a hidden window, a DComp visual for it and a `width` x `height` surface rendering
+    /// to that visual, with an instance restricted to `backends`.
+    pub fn new(
+        backends: wgpu::Backends,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> crate::Result<Self> {
+        unsafe {
+            // Ignored, the test thread may already have initialized COM in another apartment model.
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+
+            let window_class = s!("headless");
+            let wc = WNDCLASSA {
+                hInstance: GetModuleHandleA(None)?.into(),
+                lpszClassName: window_class,
+                lpfnWndProc: Some(wndproc),
+                ..Default::default()
+            };
+            if RegisterClassA(&wc) == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+                return Err(windows::core::Error::from_win32().into());
+            }
+
+            // Not message-only, DComp can't target those, but never shown or activated.
+            let hwnd = CreateWindowExA(
+                WS_EX_NOREDIRECTIONBITMAP | WS_EX_NOACTIVATE | WS_EX_TOOLWINDOW,
+                window_class,
+                s!("headless"),
+                WS_POPUP,
+                0,
+                0,
+                width as i32,
+                height as i32,
+                None,
+                None,
+                None,
+                None,
+            )?;
+
+            let result = Self::create_resources(hwnd, backends, width, height, descriptor);
+            if result.is_err() {
+                let _ = DestroyWindow(hwnd);
+            }
+            result
+        }
+    }
+
+    unsafe fn create_resources(
+        hwnd: HWND,
+        backends: wgpu::Backends,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> crate::Result<Self> {
+        let device = create_device_3d(D3D_DRIVER_TYPE_HARDWARE, false).map_err(Error::D3D)?;
+        let device_2d = create_device_2d(&device).map_err(Error::D3D)?;
+        let desktop: IDCompositionDesktopDevice =
+            DCompositionCreateDevice2(&device_2d).map_err(Error::DComp)?;
+
+        let target = desktop
+            .CreateTargetForHwnd(hwnd, true)
+            .map_err(Error::DComp)?;
+        let visual = desktop.CreateVisual().map_err(Error::DComp)?;
+        target.SetRoot(&visual).map_err(Error::DComp)?;
+
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let state = pollster::block_on(SurfaceState::new(
+            &instance,
+            visual.as_raw(),
+            width,
+            height,
+            descriptor,
+        ))?;
+
+        desktop.Commit().map_err(Error::DComp)?;
+
+        Ok(Self {
+            state,
+            visual,
+            target,
+            desktop,
+            device,
+            hwnd,
+        })
+    }
+
+    /// The D3D11 device backing the composition.
+    pub fn device(&self) -> &ID3D11Device {
+        &self.device
+    }
+
+    /// The DComp desktop device the visual was created from.
+    pub fn desktop(&self) -> &IDCompositionDesktopDevice {
+        &self.desktop
+    }
+
+    /// The visual the surface renders to, the root of the hidden window's target.
+    pub fn visual(&self) -> &IDCompositionVisual2 {
+        &self.visual
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+}
+
+extern "system" fn wndproc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe { DefWindowProcA(window, message, wparam, lparam) }
+}
+
+impl Drop for HeadlessSurface {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.target.SetRoot(None);
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
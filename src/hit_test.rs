@@ -0,0 +1,97 @@
+use windows::Win32::{Foundation::LRESULT, UI::WindowsAndMessaging::*};
+
+/// What a point of the window acts as for the mouse, the answer to `WM_NCHITTEST`.
+///
+/// Lets borderless windows, which have no frame of their own, be moved and resized by dragging
+/// parts of their client area. See [`crate::Window::on_hit_test`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HitTest {
+    /// Regular client area receiving mouse input.
+    Client,
+    /// Drags move the window, double clicks maximize it.
+    Caption,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Mouse input goes to the window below, if it belongs to the same thread.
+    Transparent,
+}
+
+impl HitTest {
+    pub(crate) fn to_lresult(self) -> LRESULT {
+        let code = match self {
+            HitTest::Client => HTCLIENT,
+            HitTest::Caption => HTCAPTION,
+            HitTest::Left => HTLEFT,
+            HitTest::Right => HTRIGHT,
+            HitTest::Top => HTTOP,
+            HitTest::Bottom => HTBOTTOM,
+            HitTest::TopLeft => HTTOPLEFT,
+            HitTest::TopRight => HTTOPRIGHT,
+            HitTest::BottomLeft => HTBOTTOMLEFT,
+            HitTest::BottomRight => HTBOTTOMRIGHT,
+            HitTest::Transparent => return LRESULT(HTTRANSPARENT as isize),
+        };
+        LRESULT(code as isize)
+    }
+}
+
+/// The alpha of each pixel of a frame, telling the transparent parts of a window, which mouse
+/// input passes through, from the parts capturing it. See [`crate::Window::set_alpha_hit_test`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlphaMask {
+    width: u32,
+    height: u32,
+    alpha: Vec<u8>,
+}
+
+impl AlphaMask {
+    /// A `width` x `height` mask with the `alpha` of each pixel, row by row.
+    ///
+    /// # Panics
+    ///
+    /// If `alpha` doesn't have `width * height` values.
+    pub fn new(width: u32, height: u32, alpha: Vec<u8>) -> Self {
+        assert_eq!(alpha.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            alpha,
+        }
+    }
+
+    /// The mask of `width` x `height` pixels in RGBA order, like frames read back by
+    /// [`crate::SurfaceState::read_alpha_mask_with`].
+    ///
+    /// # Panics
+    ///
+    /// If `rgba` doesn't have `width * height * 4` bytes.
+    pub fn from_rgba(width: u32, height: u32, rgba: &[u8]) -> Self {
+        Self::new(
+            width,
+            height,
+            rgba.chunks_exact(4).map(|pixel| pixel[3]).collect(),
+        )
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The alpha of the pixel at `x`, `y`, `None` outside of the mask.
+    pub fn alpha(&self, x: u32, y: u32) -> Option<u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.alpha[y as usize * self.width as usize + x as usize])
+    }
+}
@@ -0,0 +1,271 @@
+use windows::Win32::{
+    Foundation::*,
+    Graphics::Gdi::ScreenToClient,
+    System::SystemServices::*,
+    UI::{
+        Input::{KeyboardAndMouse::VIRTUAL_KEY, *},
+        WindowsAndMessaging::*,
+    },
+};
+
+use crate::{hiword, loword};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// The mouse buttons held down when a [`MouseEvent`] was generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// A mouse event in the window's client area.
+///
+/// Positions are in physical pixels relative to the top-left of the client area, like the
+/// surface itself. Use [`MouseEvent::logical_position`] for DPI-independent coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MouseEvent {
+    Moved {
+        position: (i32, i32),
+        buttons: MouseButtons,
+    },
+    Pressed {
+        button: MouseButton,
+        position: (i32, i32),
+        buttons: MouseButtons,
+    },
+    Released {
+        button: MouseButton,
+        position: (i32, i32),
+        buttons: MouseButtons,
+    },
+}
+
+impl MouseEvent {
+    /// Decodes a client area mouse message, or returns `None` for any other message.
+    pub(crate) fn from_message(message: u32, wparam: WPARAM, lparam: LPARAM) -> Option<Self> {
+        // Coordinates are signed, they can be negative while the mouse is captured.
+        let position = (
+            loword(lparam.0 as u32) as i16 as i32,
+            hiword(lparam.0 as u32) as i16 as i32,
+        );
+        let flags = MODIFIERKEYS_FLAGS(wparam.0 as u32);
+        let buttons = MouseButtons {
+            left: flags.contains(MK_LBUTTON),
+            right: flags.contains(MK_RBUTTON),
+            middle: flags.contains(MK_MBUTTON),
+        };
+
+        let event = match message {
+            WM_MOUSEMOVE => MouseEvent::Moved { position, buttons },
+            WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN => MouseEvent::Pressed {
+                button: button(message),
+                position,
+                buttons,
+            },
+            WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP => MouseEvent::Released {
+                button: button(message),
+                position,
+                buttons,
+            },
+            _ => return None,
+        };
+        Some(event)
+    }
+
+    /// The cursor position in physical pixels.
+    pub fn position(&self) -> (i32, i32) {
+        match self {
+            MouseEvent::Moved { position, .. }
+            | MouseEvent::Pressed { position, .. }
+            | MouseEvent::Released { position, .. } => *position,
+        }
+    }
+
+    /// The cursor position divided by `scale_factor`, usually [`crate::Window::scale_factor`].
+    pub fn logical_position(&self, scale_factor: f64) -> (f64, f64) {
+        let (x, y) = self.position();
+        (x as f64 / scale_factor, y as f64 / scale_factor)
+    }
+}
+
+/// A mouse wheel rotation, from `WM_MOUSEWHEEL` or `WM_MOUSEHWHEEL`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollEvent {
+    /// The rotation in notches, positive to the right and away from the user. High-resolution
+    /// wheels report fractions of a notch.
+    pub delta: (f32, f32),
+    /// The cursor position in physical pixels relative to the client area, like
+    /// [`MouseEvent::position`].
+    pub position: (i32, i32),
+    pub buttons: MouseButtons,
+}
+
+impl ScrollEvent {
+    /// Decodes a wheel message sent to `hwnd`, or returns `None` for any other message.
+    pub(crate) fn from_message(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> Option<Self> {
+        let notches = hiword(wparam.0 as u32) as i16 as f32 / WHEEL_DELTA as f32;
+        let delta = match message {
+            WM_MOUSEWHEEL => (0.0, notches),
+            WM_MOUSEHWHEEL => (notches, 0.0),
+            _ => return None,
+        };
+
+        // Unlike other mouse messages, wheel messages carry screen coordinates.
+        let mut position = POINT {
+            x: loword(lparam.0 as u32) as i16 as i32,
+            y: hiword(lparam.0 as u32) as i16 as i32,
+        };
+        unsafe {
+            let _ = ScreenToClient(hwnd, &mut position);
+        }
+
+        let flags = MODIFIERKEYS_FLAGS(loword(wparam.0 as u32) as u32);
+        Some(ScrollEvent {
+            delta,
+            position: (position.x, position.y),
+            buttons: MouseButtons {
+                left: flags.contains(MK_LBUTTON),
+                right: flags.contains(MK_RBUTTON),
+                middle: flags.contains(MK_MBUTTON),
+            },
+        })
+    }
+}
+
+fn button(message: u32) -> MouseButton {
+    match message {
+        WM_LBUTTONDOWN | WM_LBUTTONUP => MouseButton::Left,
+        WM_RBUTTONDOWN | WM_RBUTTONUP => MouseButton::Right,
+        _ => MouseButton::Middle,
+    }
+}
+
+/// A key press or release, identified by its virtual key code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: VIRTUAL_KEY,
+    pub pressed: bool,
+    /// Whether this is an auto-repeated press of a key that is held down.
+    pub repeat: bool,
+}
+
+impl KeyEvent {
+    /// Decodes a `WM_KEYDOWN` or `WM_KEYUP` message, or returns `None` for any other message.
+    pub(crate) fn from_message(message: u32, wparam: WPARAM, lparam: LPARAM) -> Option<Self> {
+        let pressed = match message {
+            WM_KEYDOWN => true,
+            WM_KEYUP => false,
+            _ => return None,
+        };
+
+        Some(KeyEvent {
+            key: VIRTUAL_KEY(wparam.0 as u16),
+            pressed,
+            // Bit 30 holds the previous key state.
+            repeat: pressed && lparam.0 & (1 << 30) != 0,
+        })
+    }
+}
+
+/// The HID usage page and usage of mice, as registered for raw input.
+const HID_USAGE_PAGE_GENERIC: u16 = 0x01;
+const HID_USAGE_GENERIC_MOUSE: u16 = 0x02;
+
+/// Relative mouse motion read from raw input, before pointer acceleration and unaffected by the
+/// cursor reaching the edge of the screen, e.g. for camera controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MouseMotion {
+    /// The motion in mouse counts, positive to the right and down.
+    pub delta: (i32, i32),
+}
+
+impl MouseMotion {
+    /// Registers the mouse for raw input, so `WM_INPUT` is sent to whichever window of the
+    /// process has keyboard focus.
+    pub(crate) fn register() -> windows::core::Result<()> {
+        let device = RAWINPUTDEVICE {
+            usUsagePage: HID_USAGE_PAGE_GENERIC,
+            usUsage: HID_USAGE_GENERIC_MOUSE,
+            dwFlags: RAWINPUTDEVICE_FLAGS(0),
+            hwndTarget: HWND::default(),
+        };
+        unsafe { RegisterRawInputDevices(&[device], size_of::<RAWINPUTDEVICE>() as u32) }
+    }
+
+    /// Decodes the relative motion of a `WM_INPUT` message, or returns `None` for other input,
+    /// e.g. from tablets reporting absolute positions.
+    pub(crate) fn from_raw_input(lparam: LPARAM) -> Option<Self> {
+        let input = HRAWINPUT(lparam.0 as _);
+        let header_size = size_of::<RAWINPUTHEADER>() as u32;
+
+        unsafe {
+            // Queries the size first, mouse input usually fits `RAWINPUT` but other devices'
+            // can be larger.
+            let mut size = 0;
+            GetRawInputData(input, RID_INPUT, None, &mut size, header_size);
+            // `u64`s, so the buffer is aligned for `RAWINPUT`.
+            let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+            if GetRawInputData(
+                input,
+                RID_INPUT,
+                Some(buffer.as_mut_ptr() as _),
+                &mut size,
+                header_size,
+            ) == u32::MAX
+            {
+                return None;
+            }
+
+            let input = &*(buffer.as_ptr() as *const RAWINPUT);
+            if input.header.dwType != RIM_TYPEMOUSE.0 {
+                return None;
+            }
+            let mouse = &input.data.mouse;
+            if mouse.usFlags.0 & MOUSE_MOVE_ABSOLUTE.0 != 0 {
+                return None;
+            }
+            if mouse.lLastX == 0 && mouse.lLastY == 0 {
+                // Button and wheel input without motion.
+                return None;
+            }
+            Some(MouseMotion {
+                delta: (mouse.lLastX, mouse.lLastY),
+            })
+        }
+    }
+}
+
+/// Decodes the UTF-16 code units of consecutive `WM_CHAR` messages, which split characters
+/// outside the Basic Multilingual Plane into a surrogate pair sent as two messages.
+#[derive(Default)]
+pub(crate) struct Utf16Decoder {
+    high_surrogate: Option<u16>,
+}
+
+impl Utf16Decoder {
+    /// Returns the character completed by `unit`, if any. A high surrogate is held until the
+    /// next unit, unpaired surrogates are dropped.
+    pub fn push(&mut self, unit: u16) -> Option<char> {
+        match (self.high_surrogate.take(), unit) {
+            (_, 0xd800..=0xdbff) => {
+                self.high_surrogate = Some(unit);
+                None
+            }
+            (Some(high), 0xdc00..=0xdfff) => char::decode_utf16([high, unit]).next()?.ok(),
+            // Lone low surrogates aren't valid `char`s.
+            (_, unit) => char::from_u32(unit as u32),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use windows::{
+    core::*,
+    Win32::Graphics::{
+        Direct2D::ID2D1DeviceContext, Direct3D11::ID3D11Device, DirectComposition::*,
+    },
+};
+
+use crate::{
+    window::{new_surface_state, RenderHandler},
+    CompositionSurface, D2DSurface, Error, SharedDevice, SurfaceState, SurfaceStateDescriptor,
+    DEFAULT_CLEAR_COLOR,
+};
+
+/// A user callback drawing a frame of a [`D2DSurface`].
+pub(crate) type DrawHandler = Box<dyn FnMut(&ID2D1DeviceContext)>;
+
+/// Identifies a layer added with [`crate::Window::add_layer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerId(pub(crate) usize);
+
+/// What a layer's visual shows.
+// A window only has a handful of layers, boxing the state isn't worth the indirection.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum LayerContent {
+    /// A swapchain presented through its own [`SurfaceState`].
+    Swapchain(SurfaceState),
+    /// A [`CompositionSurface`] rendered with the window's device.
+    CompositionSurface(CompositionSurface),
+    /// A [`D2DSurface`] drawn with the D2D device the DComp device was created from.
+    D2D(D2DSurface),
+}
+
+/// Which kind of [`LayerContent`] a layer is created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LayerKind {
+    Swapchain,
+    CompositionSurface,
+    /// A [`LayerContent::CompositionSurface`] that is virtual, see
+    /// [`CompositionSurface::new_virtual`].
+    VirtualSurface,
+    D2D,
+}
+
+/// A visual stacked above the window's wgpu visual.
+pub(crate) struct Layer {
+    pub offset: (f32, f32),
+    pub size: (u32, u32),
+    pub kind: LayerKind,
+    pub render_handler: RenderHandler,
+    /// The callback drawing a [`LayerKind::D2D`] layer.
+    pub draw_handler: DrawHandler,
+    /// The clear color of a swapchain layer's surface.
+    pub clear_color: wgpu::Color,
+    /// Whether the visual is in the tree, see [`crate::Window::set_layer_visible`].
+    pub visible: bool,
+    /// Declared before `visual`, so the surface is released before the visual it was created from.
+    pub content: Option<LayerContent>,
+    pub visual: Option<IDCompositionVisual2>,
+}
+
+impl Layer {
+    pub fn new(offset: (f32, f32), size: (u32, u32), kind: LayerKind) -> Self {
+        Self {
+            offset,
+            size,
+            kind,
+            render_handler: Box::new(|state, view| state.draw(view)),
+            draw_handler: Box::new(|_| {}),
+            clear_color: DEFAULT_CLEAR_COLOR,
+            visible: true,
+            content: None,
+            visual: None,
+        }
+    }
+
+    /// Creates the layer's visual and its content, then adds the visual above the other children
+    /// of `root` if the layer is visible. Composition surfaces are shared between `device_3d` and
+    /// `window_device`, the device of the window's surface.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn create_resources(
+        &self,
+        desktop: &IDCompositionDesktopDevice,
+        root: &IDCompositionVisual2,
+        device_3d: &ID3D11Device,
+        window_device: &wgpu::Device,
+        instance: &wgpu::Instance,
+        shared: Option<&SharedDevice>,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> crate::Result<(IDCompositionVisual2, LayerContent)> {
+        let visual = desktop.CreateVisual().map_err(Error::DComp)?;
+        visual.SetOffsetX2(self.offset.0).map_err(Error::DComp)?;
+        visual.SetOffsetY2(self.offset.1).map_err(Error::DComp)?;
+
+        let (width, height) = self.size;
+        let content = match self.kind {
+            LayerKind::Swapchain => {
+                let mut state = new_surface_state(
+                    instance,
+                    shared,
+                    visual.as_raw(),
+                    width,
+                    height,
+                    descriptor,
+                )?;
+                state.set_clear_color(self.clear_color);
+                LayerContent::Swapchain(state)
+            }
+            LayerKind::CompositionSurface | LayerKind::VirtualSurface => {
+                let surface = if self.kind == LayerKind::VirtualSurface {
+                    CompositionSurface::new_virtual(
+                        desktop,
+                        device_3d,
+                        window_device,
+                        width,
+                        height,
+                    )?
+                } else {
+                    CompositionSurface::new(desktop, device_3d, window_device, width, height)?
+                };
+                visual.SetContent(surface.surface()).map_err(Error::DComp)?;
+                LayerContent::CompositionSurface(surface)
+            }
+            LayerKind::D2D => {
+                let surface = D2DSurface::new(desktop, width, height)?;
+                visual.SetContent(surface.surface()).map_err(Error::DComp)?;
+                LayerContent::D2D(surface)
+            }
+        };
+
+        // Only added once the surface exists, so a failure leaves nothing in the tree.
+        if self.visible {
+            root.AddVisual(&visual, false, None).map_err(Error::DComp)?;
+        }
+        Ok((visual, content))
+    }
+}
@@ -0,0 +1,59 @@
+//! A wgpu surface composited through DirectComposition.
+//!
+//! [`Window`] owns a Win32 window with a DComp visual tree, and [`SurfaceState`] wraps a wgpu
+//! surface created from a composition visual. [`App`] implementations can be run in a window
+//! with [`Window::run_app`].
+
+mod app;
+mod bench;
+mod capture;
+mod composition_surface;
+mod compute;
+mod d2d_surface;
+mod device;
+mod error;
+#[cfg(feature = "headless")]
+mod headless;
+mod hit_test;
+mod input;
+mod layer;
+mod offscreen;
+mod shader;
+mod stats;
+mod surface;
+mod timing;
+mod uniforms;
+mod window;
+
+pub use app::{App, DefaultApp};
+pub use bench::BenchReport;
+pub use capture::CaptureError;
+pub use composition_surface::CompositionSurface;
+pub use d2d_surface::D2DSurface;
+pub use device::{create_device_2d, create_device_3d, SharedDevice};
+pub use error::{Error, Result};
+#[cfg(feature = "headless")]
+pub use headless::HeadlessSurface;
+pub use hit_test::{AlphaMask, HitTest};
+pub use input::{KeyEvent, MouseButton, MouseButtons, MouseEvent, MouseMotion, ScrollEvent};
+pub use layer::LayerId;
+pub use shader::ShaderError;
+pub use surface::{
+    SurfaceState, SurfaceStateDescriptor, SurfaceStateError, DEFAULT_CLEAR_COLOR, DEPTH_FORMAT,
+};
+pub use timing::{CompositionStatistics, PresentStatistics};
+pub use uniforms::Uniforms;
+pub use window::{run_event_loop, LoopMode, Window, WindowBuilder};
+
+/// The target of the crate's log messages.
+pub(crate) const LOG_TARGET: &str = "wgpu_dcomp";
+
+#[inline(always)]
+pub(crate) const fn loword(x: u32) -> u16 {
+    (x & 0xffff) as u16
+}
+
+#[inline(always)]
+pub(crate) const fn hiword(x: u32) -> u16 {
+    ((x >> 16) & 0xffff) as u16
+}
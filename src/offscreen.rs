@@ -0,0 +1,237 @@
+use wgpu::{
+    BindGroup, BindGroupLayout, Device, Queue, RenderPipeline, Sampler, SurfaceConfiguration,
+    TextureFormat, TextureView,
+};
+
+const COMPOSITE_SHADER: &str = r#"
+@group(0) @binding(0) var scene: texture_2d<f32>;
+@group(0) @binding(1) var scene_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// A triangle covering the whole target.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(scene, scene_sampler, in.uv);
+}
+
+@fragment
+fn fs_premultiply(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(scene, scene_sampler, in.uv);
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+"#;
+
+/// A fullscreen pass drawing a texture over all of a target, replacing its content.
+pub(crate) struct Composite {
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    pipeline: RenderPipeline,
+}
+
+impl Composite {
+    /// Creates the pass for targets of `format`, multiplying the colors by their alpha on the way
+    /// if `premultiply` is set, for textures with straight alpha.
+    pub fn new(device: &Device, format: TextureFormat, premultiply: bool) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("composite"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("composite"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("composite"),
+            source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("composite"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("composite"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(if premultiply {
+                    "fs_premultiply"
+                } else {
+                    "fs_main"
+                }),
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            layout,
+            sampler,
+            pipeline,
+        }
+    }
+
+    /// Binds `source`, which must be filterable, to be drawn by [`Composite::encode`].
+    pub fn bind_group(&self, device: &Device, source: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("composite"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    /// Encodes the pass drawing the texture of `source` over all of `target`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &BindGroup,
+        target: &TextureView,
+    ) {
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("composite"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderpass.set_pipeline(&self.pipeline);
+        renderpass.set_bind_group(0, source, &[]);
+        renderpass.draw(0..3, 0..1);
+    }
+}
+
+/// An intermediate texture frames are rendered to instead of the surface texture, then drawn
+/// to it by a [`Composite`] pass, enabled with [`crate::SurfaceState::set_offscreen`].
+///
+/// The texture has the surface's size and view format, so pipelines built for the surface can
+/// render to it unchanged. Its alpha is copied as is, staying premultiplied for DComp, unless the
+/// target premultiplies straight alpha, see [`crate::SurfaceStateDescriptor::premultiply_alpha`].
+pub(crate) struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: TextureView,
+    bind_group: BindGroup,
+    composite: Composite,
+}
+
+impl OffscreenTarget {
+    pub fn new(
+        device: &Device,
+        config: &SurfaceConfiguration,
+        format: TextureFormat,
+        premultiply: bool,
+    ) -> Self {
+        let composite = Composite::new(device, format, premultiply);
+        let texture = create_texture(device, config, format);
+        let view = texture.create_view(&Default::default());
+        Self {
+            bind_group: composite.bind_group(device, &view),
+            texture,
+            view,
+            composite,
+        }
+    }
+
+    /// Recreates the texture at the size of `config`.
+    pub fn resize(&mut self, device: &Device, config: &SurfaceConfiguration) {
+        self.texture = create_texture(device, config, self.texture.format());
+        self.view = self.texture.create_view(&Default::default());
+        self.bind_group = self.composite.bind_group(device, &self.view);
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    /// Encodes and submits the pass drawing the texture over all of `target`.
+    pub fn composite(&self, device: &Device, queue: &Queue, target: &TextureView) {
+        let mut encoder = device.create_command_encoder(&Default::default());
+        self.composite
+            .encode(&mut encoder, &self.bind_group, target);
+        queue.submit([encoder.finish()]);
+    }
+}
+
+fn create_texture(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    format: TextureFormat,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
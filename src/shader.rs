@@ -0,0 +1,192 @@
+use std::{fmt, io, path::PathBuf, thread, time::Duration};
+
+use wgpu::{BindGroupLayout, Device, RenderPipeline, TextureFormat};
+use windows::Win32::{
+    Foundation::*,
+    UI::WindowsAndMessaging::{PostMessageA, WM_APP},
+};
+
+/// Posted to a window by [`watch_shader`] when its shader file has been modified.
+pub(crate) const WM_SHADER_CHANGED: u32 = WM_APP + 1;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The built-in shader drawing a single triangle.
+pub(crate) const TRIANGLE_SHADER: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let x = f32(i32(index) - 1) * 0.5;
+    let y = f32(i32(index & 1u) * 2 - 1) * 0.5;
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0, 0.0, 1.0, 1.0);
+}
+"#;
+
+/// Prepended to the fragment shaders of [`crate::SurfaceState::new_shadertoy`]: the vertex shader
+/// of a triangle covering the whole surface, and a fragment shader setting Shadertoy's inputs
+/// before calling the user's `mainImage`.
+pub(crate) const SHADERTOY_PRELUDE: &str = r#"
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+    mouse: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+
+var<private> iTime: f32;
+var<private> iResolution: vec3<f32>;
+var<private> iMouse: vec4<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    iTime = uniforms.time;
+    iResolution = vec3<f32>(uniforms.resolution, 1.0);
+    iMouse = uniforms.mouse;
+    // Shadertoy's origin is the bottom left, and DComp expects premultiplied colors.
+    let color = mainImage(vec2<f32>(position.x, uniforms.resolution.y - position.y));
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+"#;
+
+/// An error loading or compiling a WGSL shader.
+#[derive(Debug)]
+pub enum ShaderError {
+    /// The shader file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// wgpu rejected the shader module or the pipeline built from it.
+    Compile(wgpu::Error),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, source } => {
+                write!(f, "failed to read shader {}: {source}", path.display())
+            }
+            ShaderError::Compile(error) => write!(f, "failed to compile shader: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io { source, .. } => Some(source),
+            ShaderError::Compile(error) => Some(error),
+        }
+    }
+}
+
+/// Reads the WGSL source at `path`.
+pub(crate) fn read_shader(path: PathBuf) -> Result<String, ShaderError> {
+    std::fs::read_to_string(&path).map_err(|source| ShaderError::Io { path, source })
+}
+
+/// Builds a pipeline drawing with the `vs_main` and `fs_main` entry points of `source` into a
+/// `format` color target with `sample_count` samples, depth tested against a `depth_format`
+/// attachment if there is one. Colors are blended with `blend`, which must match whether `fs_main`
+/// returns straight or premultiplied alpha.
+/// `uniform_layout` is bound at group 0, shaders may leave it unused.
+///
+/// Validation errors are captured in an error scope and returned instead of reaching the device's
+/// uncaptured error handler, which panics by default.
+pub(crate) async fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    depth_format: Option<TextureFormat>,
+    sample_count: u32,
+    blend: wgpu::BlendState,
+    uniform_layout: &BindGroupLayout,
+    source: &str,
+) -> Result<RenderPipeline, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("shader"),
+        bind_group_layouts: &[uniform_layout],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("shader"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            compilation_options: Default::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: Default::default(),
+        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+        cache: None,
+    });
+
+    match device.pop_error_scope().await {
+        Some(error) => Err(ShaderError::Compile(error)),
+        None => Ok(pipeline),
+    }
+}
+
+/// Spawns a thread polling the modification time of `path`, posting [`WM_SHADER_CHANGED`] to
+/// `hwnd` whenever it changes. The thread exits once the window is destroyed.
+pub(crate) fn watch_shader(path: PathBuf, hwnd: HWND) {
+    let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    // `HWND` is not `Send`, but posting messages to it from any thread is fine.
+    let hwnd = hwnd.0 as isize;
+
+    thread::spawn(move || {
+        let mut last_modified = modified(&path);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            let posted =
+                unsafe { PostMessageA(HWND(hwnd as _), WM_SHADER_CHANGED, WPARAM(0), LPARAM(0)) };
+            if posted.is_err() {
+                break;
+            }
+        }
+    });
+}
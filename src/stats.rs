@@ -0,0 +1,392 @@
+use std::{
+    cell::Cell,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use wgpu::{BindGroup, BindGroupLayout, Buffer, Device, Queue, RenderPipeline, TextureFormat};
+
+/// How often the displayed averages are updated.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The characters in the glyph atlas, in order. Spaces advance without drawing anything.
+const GLYPHS: &str = "0123456789.-ACEFGMPRSUms";
+
+/// 5x7 bitmaps of [`GLYPHS`], one byte per row from the top, the leftmost pixel in bit 4.
+const FONT: [[u8; 7]; 24] = [
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f],
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11],
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e],
+];
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Physical pixels per font pixel.
+const SCALE: f32 = 2.0;
+/// The distance of the text from the top left corner and the edges of its background.
+const MARGIN: f32 = 8.0;
+const ADVANCE: f32 = (GLYPH_WIDTH + 1) as f32 * SCALE;
+const LINE_HEIGHT: f32 = (GLYPH_HEIGHT + 2) as f32 * SCALE;
+
+/// The most glyphs drawn per frame, including the background.
+const MAX_INSTANCES: usize = 64;
+
+/// Position and size in pixels, then the glyph index.
+const INSTANCE_SIZE: usize = 20;
+
+/// The glyph index drawing a translucent background instead of a glyph.
+const BACKGROUND: u32 = u32::MAX;
+
+const STATS_SHADER: &str = r#"
+struct Uniforms {
+    time: f32,
+    resolution: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+@group(1) @binding(0) var atlas: texture_2d<f32>;
+
+struct Instance {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) glyph: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) glyph: u32,
+}
+
+const GLYPH_SIZE = vec2<f32>(5.0, 7.0);
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: Instance) -> VertexOutput {
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let pixel = instance.position + corner * instance.size;
+    var out: VertexOutput;
+    out.position = vec4<f32>(pixel / uniforms.resolution * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = corner;
+    out.glyph = instance.glyph;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if in.glyph == 0xffffffffu {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.6);
+    }
+    let texel = vec2<u32>(min(in.uv * GLYPH_SIZE, GLYPH_SIZE - 1.0)) + vec2<u32>(in.glyph * 5u, 0u);
+    // Premultiplied white.
+    return vec4<f32>(textureLoad(atlas, texel, 0).r);
+}
+"#;
+
+/// Frame measurements summed over the current update interval.
+#[derive(Clone, Copy, Default)]
+struct Totals {
+    frames: u32,
+    frame_ms: f64,
+    cpu_ms: f64,
+    gpu_frames: u32,
+    gpu_ms: f64,
+}
+
+/// Draws the FPS and average frame times over the top left corner of each frame, enabled with
+/// [`crate::SurfaceState::show_stats`].
+///
+/// The frame time is measured between consecutive frames, the CPU time is spent in the render
+/// callback and the GPU time is read from the timestamp queries of
+/// [`crate::SurfaceStateDescriptor::gpu_timing`], if enabled.
+pub(crate) struct StatsOverlay {
+    pipeline: RenderPipeline,
+    atlas: BindGroup,
+    instances: Buffer,
+    last_frame: Cell<Option<Instant>>,
+    interval: Cell<(Instant, Totals)>,
+    text: Cell<Option<String>>,
+}
+
+impl StatsOverlay {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        uniform_layout: &BindGroupLayout,
+    ) -> Self {
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("stats atlas"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let atlas = create_atlas(device, queue);
+        let atlas = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("stats atlas"),
+            layout: &atlas_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &atlas.create_view(&Default::default()),
+                ),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("stats"),
+            source: wgpu::ShaderSource::Wgsl(STATS_SHADER.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("stats"),
+            bind_group_layouts: &[uniform_layout, &atlas_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("stats"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: INSTANCE_SIZE as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Uint32,
+                    ],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("stats instances"),
+            size: (MAX_INSTANCES * INSTANCE_SIZE) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            pipeline,
+            atlas,
+            instances,
+            last_frame: Cell::new(None),
+            interval: Cell::new((Instant::now(), Totals::default())),
+            text: Cell::new(None),
+        }
+    }
+
+    /// Records a frame whose render callback took `cpu`, and whose GPU time, if measured, was
+    /// `gpu_ms`.
+    pub fn record(&self, cpu: Duration, gpu_ms: Option<f64>) {
+        let now = Instant::now();
+        let Some(last_frame) = self.last_frame.replace(Some(now)) else {
+            return;
+        };
+
+        let (start, mut totals) = self.interval.get();
+        totals.frames += 1;
+        totals.frame_ms += (now - last_frame).as_secs_f64() * 1000.0;
+        totals.cpu_ms += cpu.as_secs_f64() * 1000.0;
+        if let Some(gpu_ms) = gpu_ms {
+            totals.gpu_frames += 1;
+            totals.gpu_ms += gpu_ms;
+        }
+
+        let elapsed = now - start;
+        if elapsed < UPDATE_INTERVAL {
+            self.interval.set((start, totals));
+            return;
+        }
+
+        let frames = totals.frames as f64;
+        let mut text = format!(
+            "FPS {:.1}\nFRAME {:.2} ms\nCPU {:.2} ms\nGPU ",
+            frames / elapsed.as_secs_f64(),
+            totals.frame_ms / frames,
+            totals.cpu_ms / frames,
+        );
+        if totals.gpu_frames > 0 {
+            let _ = write!(text, "{:.2} ms", totals.gpu_ms / totals.gpu_frames as f64);
+        } else {
+            text.push('-');
+        }
+        self.text.set(Some(text));
+        self.interval.set((now, Totals::default()));
+    }
+
+    /// Encodes and submits a pass drawing the last averages over `view`. Nothing is drawn until
+    /// the first update interval has passed.
+    pub fn draw(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        view: &wgpu::TextureView,
+        uniforms: &BindGroup,
+    ) {
+        let Some(text) = self.text.take() else {
+            return;
+        };
+        let instances = layout_text(&text);
+        self.text.set(Some(text));
+
+        queue.write_buffer(&self.instances, 0, &instances);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("stats"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderpass.set_pipeline(&self.pipeline);
+        renderpass.set_bind_group(0, uniforms, &[]);
+        renderpass.set_bind_group(1, &self.atlas, &[]);
+        renderpass.set_vertex_buffer(0, self.instances.slice(..));
+        renderpass.draw(0..4, 0..(instances.len() / INSTANCE_SIZE) as u32);
+        drop(renderpass);
+
+        queue.submit([encoder.finish()]);
+    }
+}
+
+/// Uploads [`FONT`] as a single row of glyphs, one byte per pixel.
+fn create_atlas(device: &Device, queue: &Queue) -> wgpu::Texture {
+    let width = GLYPH_WIDTH * FONT.len() as u32;
+    let size = wgpu::Extent3d {
+        width,
+        height: GLYPH_HEIGHT,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("stats atlas"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let mut pixels = Vec::with_capacity((width * GLYPH_HEIGHT) as usize);
+    for row in 0..GLYPH_HEIGHT as usize {
+        for glyph in &FONT {
+            for column in (0..GLYPH_WIDTH).rev() {
+                pixels.push(if glyph[row] >> column & 1 == 1 {
+                    255
+                } else {
+                    0
+                });
+            }
+        }
+    }
+
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(width),
+            rows_per_image: None,
+        },
+        size,
+    );
+    texture
+}
+
+/// The instances drawing a background behind `text`, then its glyphs. Characters missing from
+/// [`GLYPHS`] are skipped, as is anything beyond [`MAX_INSTANCES`].
+fn layout_text(text: &str) -> Vec<u8> {
+    let mut instances = Vec::with_capacity(MAX_INSTANCES * INSTANCE_SIZE);
+    let mut push = |position: [f32; 2], size: [f32; 2], glyph: u32| {
+        if instances.len() < MAX_INSTANCES * INSTANCE_SIZE {
+            for value in position.into_iter().chain(size) {
+                instances.extend_from_slice(&value.to_ne_bytes());
+            }
+            instances.extend_from_slice(&glyph.to_ne_bytes());
+        }
+    };
+
+    let columns = text.lines().map(|line| line.len()).max().unwrap_or(0);
+    let rows = text.lines().count();
+    push(
+        [0.0, 0.0],
+        [
+            columns as f32 * ADVANCE + 2.0 * MARGIN,
+            rows as f32 * LINE_HEIGHT + 2.0 * MARGIN,
+        ],
+        BACKGROUND,
+    );
+
+    let glyph_size = [GLYPH_WIDTH as f32 * SCALE, GLYPH_HEIGHT as f32 * SCALE];
+    for (row, line) in text.lines().enumerate() {
+        for (column, char) in line.chars().enumerate() {
+            if let Some(glyph) = GLYPHS.find(char) {
+                let position = [
+                    MARGIN + column as f32 * ADVANCE,
+                    MARGIN + row as f32 * LINE_HEIGHT,
+                ];
+                push(position, glyph_size, glyph as u32);
+            }
+        }
+    }
+    instances
+}
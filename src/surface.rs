@@ -0,0 +1,1423 @@
+use std::{
+    cell::Cell,
+    ffi::c_void,
+    fmt,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use log::{debug, info, warn};
+use wgpu::{Device, Queue, RenderPipeline, Surface, SurfaceConfiguration, TextureFormat};
+use windows::Win32::Graphics::Dxgi::Common::*;
+
+use crate::{
+    compute::ComputeTarget,
+    offscreen::OffscreenTarget,
+    shader::{create_pipeline, read_shader, ShaderError, SHADERTOY_PRELUDE, TRIANGLE_SHADER},
+    stats::StatsOverlay,
+    timing::{GpuTimer, PresentStatistics},
+    uniforms::{UniformBinding, Uniforms},
+    SharedDevice, LOG_TARGET,
+};
+
+/// The frame latencies wgpu's DX12 backend accepts, requests outside of it are clamped.
+const FRAME_LATENCY_RANGE: RangeInclusive<u32> = 1..=16;
+
+/// The swapchain buffer counts reachable through the frame latency on DX12, which creates one
+/// buffer more than the latency and at most 16.
+const SWAPCHAIN_BUFFER_COUNT_RANGE: RangeInclusive<u32> = 2..=16;
+
+/// The format of the depth buffer created when [`SurfaceStateDescriptor::depth`] is enabled.
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// The color [`SurfaceState::clear`] clears to unless changed: semi-transparent red, so the
+/// composited content is easy to tell apart from the window behind it.
+pub const DEFAULT_CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 1.,
+    g: 0.,
+    b: 0.,
+    a: 0.5,
+};
+
+/// Options for creating a [`SurfaceState`].
+#[derive(Clone, Debug)]
+pub struct SurfaceStateDescriptor {
+    desired_maximum_frame_latency: u32,
+    pub(crate) alpha_mode: wgpu::CompositeAlphaMode,
+    required_features: wgpu::Features,
+    required_limits: wgpu::Limits,
+    pub(crate) present_mode: wgpu::PresentMode,
+    formats: Vec<TextureFormat>,
+    gpu_timing: bool,
+    depth: bool,
+    adapter_name_filter: Option<String>,
+    swapchain_buffer_count: Option<u32>,
+    premultiply_alpha: bool,
+    sample_count: u32,
+}
+
+impl Default for SurfaceStateDescriptor {
+    fn default() -> Self {
+        Self {
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::PreMultiplied,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            formats: vec![TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm],
+            gpu_timing: false,
+            depth: false,
+            adapter_name_filter: None,
+            swapchain_buffer_count: None,
+            premultiply_alpha: false,
+            sample_count: 1,
+        }
+    }
+}
+
+impl SurfaceStateDescriptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets [`wgpu::SurfaceConfiguration::desired_maximum_frame_latency`]. Defaults to 2.
+    ///
+    /// On DX12 this also sets the swapchain buffer count, see
+    /// [`SurfaceStateDescriptor::swapchain_buffer_count`].
+    pub fn desired_maximum_frame_latency(mut self, latency: u32) -> Self {
+        self.desired_maximum_frame_latency = latency;
+        self
+    }
+
+    /// Sets the preferred alpha mode, used if the surface supports it. Defaults to
+    /// [`wgpu::CompositeAlphaMode::PreMultiplied`], which DComp blends over the content beneath.
+    pub fn alpha_mode(mut self, alpha_mode: wgpu::CompositeAlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+
+    /// Sets the present mode, used if the surface supports it. `Immediate` and `Mailbox` fall back
+    /// to each other, then to [`wgpu::PresentMode::Fifo`], which every surface supports.
+    /// Defaults to [`wgpu::PresentMode::AutoVsync`].
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Sets the surface formats in order of preference, the first one the surface supports is
+    /// used, or the first format the surface supports if none of them is. Defaults to
+    /// [`TextureFormat::Bgra8UnormSrgb`] then [`TextureFormat::Bgra8Unorm`], which some backends
+    /// only offer for composition surfaces.
+    ///
+    /// [`TextureFormat::Rgba16Float`] gives a linear surface for HDR content. wgpu doesn't set a
+    /// swapchain color space, so DXGI's default for the format applies, see
+    /// [`SurfaceState::color_space`].
+    pub fn formats(mut self, formats: impl Into<Vec<TextureFormat>>) -> Self {
+        self.formats = formats.into();
+        self
+    }
+
+    /// Sets the features the device is requested with. Defaults to no features.
+    pub fn required_features(mut self, features: wgpu::Features) -> Self {
+        self.required_features = features;
+        self
+    }
+
+    /// Sets the limits the device is requested with. Defaults to [`wgpu::Limits::default`].
+    pub fn required_limits(mut self, limits: wgpu::Limits) -> Self {
+        self.required_limits = limits;
+        self
+    }
+
+    /// Measures the GPU duration of frames drawn by [`SurfaceState::draw`] with timestamp
+    /// queries, see [`SurfaceState::gpu_frame_time_ms`]. Only takes effect if the adapter supports
+    /// [`wgpu::Features::TIMESTAMP_QUERY`], which is then requested. Defaults to `false`.
+    pub fn gpu_timing(mut self, gpu_timing: bool) -> Self {
+        self.gpu_timing = gpu_timing;
+        self
+    }
+
+    /// Creates a [`DEPTH_FORMAT`] depth buffer the size of the surface, cleared to 1.0 and
+    /// attached by [`SurfaceState::draw`]. Pipelines drawing in the same pass must have a
+    /// matching depth stencil state. Defaults to `false`.
+    pub fn depth(mut self, depth: bool) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Uses the first adapter whose name contains `filter`, ignoring case, e.g. `"nvidia"` to pick
+    /// the discrete GPU of a hybrid graphics laptop, instead of the one wgpu prefers. Creating
+    /// the surface fails with [`SurfaceStateError::NoMatchingAdapter`] if none matches.
+    pub fn adapter_name_filter(mut self, filter: &str) -> Self {
+        self.adapter_name_filter = Some(filter.to_owned());
+        self
+    }
+
+    /// Requests `count` swapchain buffers, overriding
+    /// [`SurfaceStateDescriptor::desired_maximum_frame_latency`].
+    ///
+    /// wgpu has no buffer count option: DX12 creates one buffer more than the frame latency, at
+    /// most 16, and limits queued frames with `SetMaximumFrameLatency`. This sets the latency to
+    /// `count - 1`, so the count is clamped to 2..=16, see
+    /// [`SurfaceState::swapchain_buffer_count`]. Other backends choose their own count from the
+    /// latency. Defaults to the count following from the frame latency, 3.
+    pub fn swapchain_buffer_count(mut self, count: u32) -> Self {
+        self.swapchain_buffer_count = Some(count);
+        self
+    }
+
+    /// Treats frames as having straight alpha, rendering them into an intermediate texture like
+    /// [`SurfaceState::set_offscreen`] and multiplying the colors by their alpha while drawing it
+    /// to the surface texture. Defaults to `false`.
+    ///
+    /// DComp blends swapchains as premultiplied, so the straight colors of partly transparent
+    /// pixels would be added at full strength instead of weighted by their alpha, showing as
+    /// fringes around transparent edges. Composition swapchains don't support
+    /// [`wgpu::CompositeAlphaMode::PostMultiplied`], DXGI's straight alpha mode, so the
+    /// conversion has to happen before presenting, at the cost of the extra pass. Colors are
+    /// multiplied in linear space, as blending does. The clear color and
+    /// [`SurfaceState::capture_png`] stay straight.
+    pub fn premultiply_alpha(mut self, premultiply: bool) -> Self {
+        self.premultiply_alpha = premultiply;
+        self
+    }
+
+    /// Draws [`SurfaceState::draw`]'s triangle, or shader, with `count` samples per pixel into a
+    /// multisampled texture resolved into the frame, smoothing its edges. Counts the surface
+    /// format doesn't support fall back to 1 with a warning, 4 is supported everywhere. Defaults
+    /// to 1.
+    ///
+    /// The multisampled texture has the surface's format but is rendered and resolved through
+    /// views of [`SurfaceState::view_format`], like the frame, so samples are averaged in linear
+    /// space rather than between sRGB encoded values, which would darken edges. The depth buffer
+    /// is multisampled too, and since the texture has the surface's size, `draw` then only
+    /// renders to views of that size.
+    pub fn sample_count(mut self, count: u32) -> Self {
+        self.sample_count = count;
+        self
+    }
+}
+
+/// An error creating a [`SurfaceState`].
+#[derive(Debug)]
+pub enum SurfaceStateError {
+    /// wgpu failed to create a surface from the composition visual.
+    SurfaceCreation(wgpu::CreateSurfaceError),
+    /// No adapter compatible with the surface was found.
+    NoAdapter,
+    /// No adapter compatible with the surface has a name containing
+    /// [`SurfaceStateDescriptor::adapter_name_filter`].
+    NoMatchingAdapter {
+        filter: String,
+        /// The names and backends of the adapters that were considered.
+        available: Vec<String>,
+    },
+    /// The adapter failed to create a device.
+    NoDevice(wgpu::RequestDeviceError),
+    /// The surface supports no texture format at all, not even a fallback for these preferred
+    /// formats.
+    UnsupportedFormat(Vec<TextureFormat>),
+    /// The adapter lacks these requested features.
+    UnsupportedFeatures(wgpu::Features),
+    /// The adapter can't satisfy these requested limits.
+    UnsupportedLimits(Vec<&'static str>),
+    /// The shader given to [`SurfaceState::new_with_shader`] failed to load.
+    Shader(ShaderError),
+}
+
+impl fmt::Display for SurfaceStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SurfaceStateError::SurfaceCreation(error) => {
+                write!(f, "failed to create surface: {error}")
+            }
+            SurfaceStateError::NoAdapter => write!(f, "no adapter compatible with the surface"),
+            SurfaceStateError::NoMatchingAdapter { filter, available } => {
+                write!(
+                    f,
+                    "no adapter compatible with the surface matches {filter:?}, available: {}",
+                    available.join(", ")
+                )
+            }
+            SurfaceStateError::NoDevice(error) => write!(f, "failed to create device: {error}"),
+            SurfaceStateError::UnsupportedFormat(formats) => {
+                write!(f, "surface supports no formats, preferred {formats:?}")
+            }
+            SurfaceStateError::UnsupportedFeatures(features) => {
+                write!(
+                    f,
+                    "adapter does not support the requested features {features:?}"
+                )
+            }
+            SurfaceStateError::UnsupportedLimits(limits) => {
+                write!(
+                    f,
+                    "adapter does not support the requested limits {limits:?}"
+                )
+            }
+            SurfaceStateError::Shader(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SurfaceStateError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SurfaceStateError::SurfaceCreation(error) => Some(error),
+            SurfaceStateError::NoDevice(error) => Some(error),
+            SurfaceStateError::Shader(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ShaderError> for SurfaceStateError {
+    fn from(error: ShaderError) -> Self {
+        SurfaceStateError::Shader(error)
+    }
+}
+
+/// A wgpu surface backed by a DirectComposition visual, along with the device used to render to it.
+pub struct SurfaceState {
+    /// The texture acquired by [`SurfaceState::acquire_ahead`] for the next frame. Declared
+    /// first, so it is released before the surface it belongs to.
+    acquired: Cell<Option<wgpu::SurfaceTexture>>,
+    pub device: Device,
+    pub queue: Queue,
+    pub surface: Surface<'static>,
+    pub surface_config: SurfaceConfiguration,
+    /// The format the surface is configured with.
+    pub format: TextureFormat,
+    /// The sRGB format frame views are created and pipelines are built with, so colors are
+    /// encoded the same whether the surface format itself is sRGB or not.
+    pub view_format: TextureFormat,
+    /// Whether [`SurfaceState::render`] draws a triangle over the clear color.
+    pub draw_triangle: bool,
+    clear_color: wgpu::Color,
+    adapter_info: wgpu::AdapterInfo,
+    capabilities: wgpu::SurfaceCapabilities,
+    pipeline: RenderPipeline,
+    depth_view: Option<wgpu::TextureView>,
+    /// The multisampled color attachment of [`SurfaceState::draw`], see
+    /// [`SurfaceStateDescriptor::sample_count`].
+    msaa_view: Option<wgpu::TextureView>,
+    sample_count: u32,
+    timer: Option<GpuTimer>,
+    present_statistics: Cell<PresentStatistics>,
+    stats: Option<StatsOverlay>,
+    offscreen: Option<OffscreenTarget>,
+    /// Whether `offscreen` premultiplies alpha, see [`SurfaceStateDescriptor::premultiply_alpha`].
+    premultiply_alpha: bool,
+    compute: Option<ComputeTarget>,
+    uniforms: UniformBinding,
+    start: Instant,
+    mouse: [f32; 4],
+    pub(crate) shader_path: Option<PathBuf>,
+    /// Whether the shader file only has a `mainImage` function, see
+    /// [`SurfaceState::new_shadertoy`].
+    pub(crate) shadertoy: bool,
+}
+
+impl SurfaceState {
+    /// Creates a surface targeting `visual`, requests an adapter and device compatible with it, and
+    /// configures the surface to `width` x `height`.
+    ///
+    /// # Safety
+    ///
+    /// `visual` must be a valid `IDCompositionVisual` pointer that outlives the returned state.
+    pub async unsafe fn new(
+        wgpu_instance: &wgpu::Instance,
+        visual: *mut c_void,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> Result<Self, SurfaceStateError> {
+        let surface = unsafe { create_surface(wgpu_instance, visual)? };
+        let adapter = request_adapter(wgpu_instance, Some(&surface), descriptor).await?;
+        let (device, queue) = request_device(&adapter, descriptor).await?;
+
+        Self::from_device(surface, &adapter, device, queue, width, height, descriptor).await
+    }
+
+    /// Like [`SurfaceState::new`], but renders with the adapter and device of `shared` instead of
+    /// requesting its own.
+    ///
+    /// # Safety
+    ///
+    /// `visual` must be a valid `IDCompositionVisual` pointer that outlives the returned state.
+    pub async unsafe fn new_with_shared_device(
+        visual: *mut c_void,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+        shared: &SharedDevice,
+    ) -> Result<Self, SurfaceStateError> {
+        let surface = unsafe { create_surface(&shared.instance, visual)? };
+        if !shared.adapter.is_surface_supported(&surface) {
+            return Err(SurfaceStateError::NoAdapter);
+        }
+
+        Self::from_device(
+            surface,
+            &shared.adapter,
+            shared.device.clone(),
+            shared.queue.clone(),
+            width,
+            height,
+            descriptor,
+        )
+        .await
+    }
+
+    /// Configures `surface` to render with `device`, which was requested from `adapter`.
+    async fn from_device(
+        surface: Surface<'static>,
+        adapter: &wgpu::Adapter,
+        device: Device,
+        queue: Queue,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+    ) -> Result<Self, SurfaceStateError> {
+        let adapter_info = adapter.get_info();
+        info!(
+            target: LOG_TARGET,
+            "adapter: {} ({:?}, {:?}), driver: {} {}",
+            adapter_info.name,
+            adapter_info.backend,
+            adapter_info.device_type,
+            adapter_info.driver,
+            adapter_info.driver_info
+        );
+
+        let gpu_timing =
+            descriptor.gpu_timing && device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+        if descriptor.gpu_timing && !gpu_timing {
+            warn!(target: LOG_TARGET, "timestamp queries are not supported, GPU timing is disabled");
+        }
+
+        let swapchain_capabilities = surface.get_capabilities(adapter);
+        let selected_format =
+            select_format(&descriptor.formats, &swapchain_capabilities.formats)
+                .ok_or_else(|| SurfaceStateError::UnsupportedFormat(descriptor.formats.clone()))?;
+        let view_format = view_format(selected_format);
+
+        debug!(
+            target: LOG_TARGET,
+            "{}",
+            CapabilitiesTable(&swapchain_capabilities)
+        );
+
+        let alpha_mode = if swapchain_capabilities
+            .alpha_modes
+            .contains(&descriptor.alpha_mode)
+        {
+            descriptor.alpha_mode
+        } else {
+            let fallback = swapchain_capabilities.alpha_modes[0];
+            warn!(
+                target: LOG_TARGET,
+                "alpha mode {:?} is not supported, falling back to {fallback:?}",
+                descriptor.alpha_mode
+            );
+            fallback
+        };
+
+        let present_mode = select_present_mode(
+            descriptor.present_mode,
+            &swapchain_capabilities.present_modes,
+        );
+
+        let latency = match descriptor.swapchain_buffer_count {
+            Some(count) => {
+                if !SWAPCHAIN_BUFFER_COUNT_RANGE.contains(&count) {
+                    warn!(
+                        target: LOG_TARGET,
+                        "swapchain_buffer_count {count} is outside of {SWAPCHAIN_BUFFER_COUNT_RANGE:?} and will be clamped"
+                    );
+                }
+                count.saturating_sub(1)
+            }
+            None => descriptor.desired_maximum_frame_latency,
+        };
+        if !FRAME_LATENCY_RANGE.contains(&latency) {
+            warn!(
+                target: LOG_TARGET,
+                "desired_maximum_frame_latency {latency} is outside of {FRAME_LATENCY_RANGE:?} and will be clamped"
+            );
+        }
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: selected_format,
+            // A zero-sized surface can't be configured, e.g. when created for a minimized window.
+            width: width.max(1),
+            height: height.max(1),
+            present_mode,
+            desired_maximum_frame_latency: latency,
+            alpha_mode,
+            view_formats: if view_format == selected_format {
+                vec![]
+            } else {
+                vec![view_format]
+            },
+        };
+
+        surface.configure(&device, &surface_config);
+
+        let depth_format = descriptor.depth.then_some(DEPTH_FORMAT);
+        let sample_count = supported_sample_count(
+            adapter,
+            &device,
+            [Some(selected_format), Some(view_format), depth_format],
+            descriptor.sample_count,
+        );
+        let uniforms = UniformBinding::new(&device);
+        let pipeline = create_pipeline(
+            &device,
+            view_format,
+            depth_format,
+            sample_count,
+            wgpu::BlendState::ALPHA_BLENDING,
+            &uniforms.layout,
+            TRIANGLE_SHADER,
+        )
+        .await?;
+        let depth_view = descriptor
+            .depth
+            .then(|| create_depth_view(&device, &surface_config, sample_count));
+        let msaa_view = (sample_count > 1)
+            .then(|| create_msaa_view(&device, &surface_config, view_format, sample_count));
+        let timer = gpu_timing.then(|| GpuTimer::new(&device, &queue));
+        let offscreen = descriptor
+            .premultiply_alpha
+            .then(|| OffscreenTarget::new(&device, &surface_config, view_format, true));
+
+        Ok(Self {
+            surface,
+            queue,
+            device,
+            surface_config,
+            format: selected_format,
+            view_format,
+            draw_triangle: false,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            adapter_info,
+            capabilities: swapchain_capabilities,
+            pipeline,
+            depth_view,
+            msaa_view,
+            sample_count,
+            timer,
+            present_statistics: Cell::default(),
+            acquired: Cell::new(None),
+            stats: None,
+            offscreen,
+            premultiply_alpha: descriptor.premultiply_alpha,
+            compute: None,
+            uniforms,
+            start: Instant::now(),
+            mouse: [0.0; 4],
+            shader_path: None,
+            shadertoy: false,
+        })
+    }
+
+    /// Like [`SurfaceState::new`], but draws with the WGSL shader at `shader_path` instead of the
+    /// built-in triangle. The shader must define `vs_main` and `fs_main` entry points and is drawn
+    /// with three vertices and no vertex buffers.
+    ///
+    /// # Safety
+    ///
+    /// `visual` must be a valid `IDCompositionVisual` pointer that outlives the returned state.
+    pub async unsafe fn new_with_shader(
+        wgpu_instance: &wgpu::Instance,
+        visual: *mut c_void,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+        shader_path: impl AsRef<Path>,
+    ) -> Result<Self, SurfaceStateError> {
+        let shader_path = shader_path.as_ref().to_owned();
+        // Fail before creating the device if the file can't be read.
+        read_shader(shader_path.clone())?;
+
+        let mut state = Self::new(wgpu_instance, visual, width, height, descriptor).await?;
+        state.shader_path = Some(shader_path);
+        state.reload_shader().await?;
+        state.draw_triangle = true;
+        Ok(state)
+    }
+
+    /// Like [`SurfaceState::new_with_shader`], but `shader_path` is a Shadertoy-style fragment
+    /// shader drawn over the whole surface, for playing with effects composited by DComp.
+    ///
+    /// The file only defines `fn mainImage(fragCoord: vec2<f32>) -> vec4<f32>`, returning the
+    /// color of the pixel at `fragCoord`, from the bottom left. It can read `iTime`,
+    /// `iResolution` and `iMouse`, which are set from the [`Uniforms`] like on Shadertoy, and
+    /// its color has straight alpha, premultiplied before the surface composites it over the
+    /// content beneath. The vertex shader and entry points are prepended to the file, so line
+    /// numbers in compile errors are offset by them.
+    ///
+    /// `iMouse` is only updated by [`SurfaceState::set_mouse`], which [`crate::Window`] calls
+    /// for mouse input.
+    ///
+    /// # Safety
+    ///
+    /// `visual` must be a valid `IDCompositionVisual` pointer that outlives the returned state.
+    pub async unsafe fn new_shadertoy(
+        wgpu_instance: &wgpu::Instance,
+        visual: *mut c_void,
+        width: u32,
+        height: u32,
+        descriptor: &SurfaceStateDescriptor,
+        shader_path: impl AsRef<Path>,
+    ) -> Result<Self, SurfaceStateError> {
+        let shader_path = shader_path.as_ref().to_owned();
+        read_shader(shader_path.clone())?;
+
+        let mut state = Self::new(wgpu_instance, visual, width, height, descriptor).await?;
+        state.shader_path = Some(shader_path);
+        state.shadertoy = true;
+        state.reload_shader().await?;
+        state.draw_triangle = true;
+        Ok(state)
+    }
+
+    /// The name, backend, device type and driver of the adapter the device was requested from.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// The color space DComp interprets the swapchain in.
+    ///
+    /// wgpu neither calls `IDXGISwapChain3::SetColorSpace1` nor exposes the swapchain it creates
+    /// for the visual, so DXGI's default for the format applies: scRGB
+    /// (`DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709`) for [`TextureFormat::Rgba16Float`], which is
+    /// enough for HDR output with values above 1.0, and sRGB otherwise. HDR10 would need the
+    /// `G2084_NONE_P2020` color space set on a 10-bit swapchain and can't be reached.
+    pub fn color_space(&self) -> DXGI_COLOR_SPACE_TYPE {
+        match self.format {
+            TextureFormat::Rgba16Float => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+            _ => DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+        }
+    }
+
+    /// The number of swapchain buffers DX12 creates for the configured frame latency, after wgpu
+    /// clamps it. Other backends may create a different number.
+    pub fn swapchain_buffer_count(&self) -> u32 {
+        let latency = self
+            .surface_config
+            .desired_maximum_frame_latency
+            .clamp(*FRAME_LATENCY_RANGE.start(), *FRAME_LATENCY_RANGE.end());
+        (latency + 1).min(*SWAPCHAIN_BUFFER_COUNT_RANGE.end())
+    }
+
+    /// Reconfigures the surface with `present_mode`, falling back like
+    /// [`SurfaceStateDescriptor::present_mode`] if it isn't supported, and returns the mode used.
+    /// The device and everything created from it are kept.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let present_mode = select_present_mode(present_mode, &self.capabilities.present_modes);
+        if present_mode != self.surface_config.present_mode {
+            debug!(target: LOG_TARGET, "present mode {present_mode:?}");
+            // Textures of the old configuration can't be presented after reconfiguring.
+            drop(self.acquired.take());
+            self.surface_config.present_mode = present_mode;
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+        present_mode
+    }
+
+    /// What the surface supports with the adapter, as queried when it was created.
+    pub fn capabilities(&self) -> &wgpu::SurfaceCapabilities {
+        &self.capabilities
+    }
+
+    /// Logs the adapter and the surface capabilities at info level, one line per property, to
+    /// be pasted into bug reports.
+    pub fn log_capabilities(&self) {
+        let info = &self.adapter_info;
+        info!(
+            target: LOG_TARGET,
+            "adapter: {} ({:?}, {:?}), driver: {} {}\n{}",
+            info.name,
+            info.backend,
+            info.device_type,
+            info.driver,
+            info.driver_info,
+            CapabilitiesTable(&self.capabilities)
+        );
+    }
+
+    /// The shader file given to [`SurfaceState::new_with_shader`] or
+    /// [`SurfaceState::new_shadertoy`].
+    pub fn shader_path(&self) -> Option<&Path> {
+        self.shader_path.as_deref()
+    }
+
+    /// Reads and recompiles the shader file given to [`SurfaceState::new_with_shader`] or
+    /// [`SurfaceState::new_shadertoy`]. On error the previous pipeline is kept. Does nothing for
+    /// the built-in shader.
+    pub async fn reload_shader(&mut self) -> Result<(), ShaderError> {
+        let Some(shader_path) = &self.shader_path else {
+            return Ok(());
+        };
+
+        let mut source = read_shader(shader_path.clone())?;
+        // The prelude premultiplies `mainImage`'s colors, blending mustn't multiply them again.
+        let blend = if self.shadertoy {
+            source.insert_str(0, SHADERTOY_PRELUDE);
+            wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING
+        } else {
+            wgpu::BlendState::ALPHA_BLENDING
+        };
+        self.pipeline = create_pipeline(
+            &self.device,
+            self.view_format,
+            self.depth_format(),
+            self.sample_count,
+            blend,
+            &self.uniforms.layout,
+            &source,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// The GPU duration of the last measured frame drawn by [`SurfaceState::draw`], in
+    /// milliseconds, if [`SurfaceStateDescriptor::gpu_timing`] is enabled and supported. Debug
+    /// builds also print the average every second.
+    pub fn gpu_frame_time_ms(&self) -> Option<f64> {
+        self.timer.as_ref().and_then(GpuTimer::last_frame_ms)
+    }
+
+    /// Blocks until all submitted work has finished on the GPU, then runs pending callbacks, such
+    /// as those passed to [`wgpu::BufferSlice::map_async`].
+    ///
+    /// Nothing drives the device from the Win32 message loop, so a `map_async` callback only runs
+    /// once the device is polled. Call this after submitting the commands writing to a buffer and
+    /// mapping it, before waiting for the callback, or it never completes.
+    pub fn poll(&self) -> wgpu::MaintainResult {
+        self.device.poll(wgpu::Maintain::Wait)
+    }
+
+    /// Draws the FPS and the average frame, CPU and GPU times over the top left corner of each
+    /// frame presented by [`SurfaceState::render_with`], in a pass after the render callback's.
+    ///
+    /// CPU time is spent in the render callback. GPU time needs
+    /// [`SurfaceStateDescriptor::gpu_timing`] and only covers frames drawn by
+    /// [`SurfaceState::draw`].
+    pub fn show_stats(&mut self, show: bool) {
+        if !show {
+            self.stats = None;
+        } else if self.stats.is_none() {
+            self.stats = Some(StatsOverlay::new(
+                &self.device,
+                &self.queue,
+                self.view_format,
+                &self.uniforms.layout,
+            ));
+        }
+    }
+
+    pub fn is_showing_stats(&self) -> bool {
+        self.stats.is_some()
+    }
+
+    /// Renders frames into an intermediate texture rather than the surface texture, then draws it
+    /// to the surface texture with a fullscreen composite pass, for post-processing.
+    ///
+    /// The render callback of [`SurfaceState::render_with`] is given a view of the intermediate
+    /// texture, which has the surface's size and [`SurfaceState::view_format`], so it renders the
+    /// scene unchanged. The stats overlay is drawn after the composite pass.
+    ///
+    /// Frames are always rendered offscreen with [`SurfaceStateDescriptor::premultiply_alpha`].
+    pub fn set_offscreen(&mut self, offscreen: bool) {
+        if self.premultiply_alpha {
+            return;
+        }
+        if !offscreen {
+            self.offscreen = None;
+        } else if self.offscreen.is_none() {
+            self.offscreen = Some(OffscreenTarget::new(
+                &self.device,
+                &self.surface_config,
+                self.view_format,
+                false,
+            ));
+        }
+    }
+
+    /// Whether frames are converted from straight to premultiplied alpha, see
+    /// [`SurfaceStateDescriptor::premultiply_alpha`].
+    pub fn premultiplies_alpha(&self) -> bool {
+        self.premultiply_alpha
+    }
+
+    pub fn is_offscreen(&self) -> bool {
+        self.offscreen.is_some()
+    }
+
+    /// The intermediate texture frames are rendered to, if [`SurfaceState::set_offscreen`] is
+    /// enabled. It can also be sampled with [`wgpu::TextureUsages::TEXTURE_BINDING`].
+    pub fn offscreen_texture(&self) -> Option<&wgpu::Texture> {
+        self.offscreen.as_ref().map(OffscreenTarget::texture)
+    }
+
+    /// Makes [`SurfaceState::draw`] dispatch a built-in compute shader writing an animated
+    /// pattern to a storage texture, then draw that texture over the whole view, instead of the
+    /// clear color and triangle. Shows compute output flowing through the DComp visual.
+    ///
+    /// Fails if the device can't write storage textures from compute shaders.
+    pub fn set_compute(&mut self, compute: bool) -> Result<(), SurfaceStateError> {
+        if !compute {
+            self.compute = None;
+        } else if self.compute.is_none() {
+            self.compute = Some(ComputeTarget::new(
+                &self.device,
+                &self.surface_config,
+                self.view_format,
+                &self.uniforms.layout,
+            )?);
+        }
+        Ok(())
+    }
+
+    pub fn is_computing(&self) -> bool {
+        self.compute.is_some()
+    }
+
+    /// Reconfigures the surface to `width` x `height`.
+    ///
+    /// Zero sizes, as reported for minimized windows, are ignored so the surface keeps its last
+    /// non-zero size until the window is restored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        // Textures of the old size can't be presented after reconfiguring.
+        drop(self.acquired.take());
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.surface.configure(&self.device, &self.surface_config);
+
+        if self.depth_view.is_some() {
+            self.depth_view = Some(create_depth_view(
+                &self.device,
+                &self.surface_config,
+                self.sample_count,
+            ));
+        }
+        if self.msaa_view.is_some() {
+            self.msaa_view = Some(create_msaa_view(
+                &self.device,
+                &self.surface_config,
+                self.view_format,
+                self.sample_count,
+            ));
+        }
+        if let Some(offscreen) = &mut self.offscreen {
+            offscreen.resize(&self.device, &self.surface_config);
+        }
+        if let Some(compute) = &mut self.compute {
+            compute.resize(&self.device, &self.surface_config);
+        }
+    }
+
+    /// The [`Uniforms`] uploaded before the next frame.
+    pub fn uniforms(&self) -> Uniforms {
+        Uniforms {
+            time: self.start.elapsed().as_secs_f32(),
+            resolution: [
+                self.surface_config.width as f32,
+                self.surface_config.height as f32,
+            ],
+            mouse: self.mouse,
+        }
+    }
+
+    /// Sets [`Uniforms::mouse`], Shadertoy's `iMouse`, in surface pixels from the bottom left.
+    pub fn set_mouse(&mut self, mouse: [f32; 4]) {
+        self.mouse = mouse;
+    }
+
+    /// The layout of the bind group holding the [`Uniforms`] buffer, for pipelines created by
+    /// render callbacks.
+    pub fn uniform_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.uniforms.layout
+    }
+
+    /// The bind group holding the [`Uniforms`] buffer, for render callbacks binding it themselves.
+    pub fn uniform_bind_group(&self) -> &wgpu::BindGroup {
+        &self.uniforms.bind_group
+    }
+
+    /// The buffer holding the [`Uniforms`], written before each frame.
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniforms.buffer
+    }
+
+    pub(crate) fn write_uniforms(&self) {
+        self.queue
+            .write_buffer(&self.uniforms.buffer, 0, &self.uniforms().to_bytes());
+    }
+
+    /// The depth buffer, if [`SurfaceStateDescriptor::depth`] is enabled, with
+    /// [`SurfaceState::sample_count`] samples.
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        self.depth_view.as_ref()
+    }
+
+    /// The samples per pixel [`SurfaceState::draw`] renders with, see
+    /// [`SurfaceStateDescriptor::sample_count`].
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    fn depth_format(&self) -> Option<TextureFormat> {
+        self.depth_view.as_ref().map(|_| DEPTH_FORMAT)
+    }
+
+    /// Renders a frame according to [`SurfaceState::draw_triangle`].
+    pub fn render(&self) -> crate::Result<()> {
+        self.render_with(|state, view| state.draw(view))
+    }
+
+    /// The color frames are cleared to before drawing, [`DEFAULT_CLEAR_COLOR`] unless changed.
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// Changes the color frames are cleared to, in linear RGBA encoded to sRGB like any other
+    /// shader output. It is written as given, so with a premultiplied alpha mode DComp treats its
+    /// color as already multiplied by alpha.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    /// Clears the current surface texture to [`SurfaceState::clear_color`] and presents it.
+    pub fn clear(&self) -> crate::Result<()> {
+        self.render_with(|state, view| state.encode(view, false))
+    }
+
+    /// Draws the triangle, or the shader given to [`SurfaceState::new_with_shader`], over the
+    /// clear color and presents it.
+    pub fn draw_triangle(&self) -> crate::Result<()> {
+        self.render_with(|state, view| state.encode(view, true))
+    }
+
+    /// Acquires the next surface texture and uploads the [`Uniforms`] for the frame, for render
+    /// loops driving [`SurfaceState::view`] and [`SurfaceState::present`] themselves.
+    ///
+    /// An `Outdated` or `Lost` surface is reconfigured and acquired again once, other errors and
+    /// a second failure are returned. A texture acquired by [`SurfaceState::acquire_ahead`] is
+    /// returned without acquiring another one.
+    pub fn acquire(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        let surface_texture = match self.acquired.take() {
+            Some(surface_texture) => surface_texture,
+            None => self.get_current_texture()?,
+        };
+        self.write_uniforms();
+        Ok(surface_texture)
+    }
+
+    /// Acquires the texture the next [`SurfaceState::acquire`] returns, unless one already is.
+    ///
+    /// On DX12, acquiring waits on the swapchain's frame latency waitable object until DXGI
+    /// accepts another frame. Acquiring ahead moves that wait before the input the frame
+    /// depends on is processed, see [`crate::Window::run_low_latency`].
+    pub fn acquire_ahead(&self) -> Result<(), wgpu::SurfaceError> {
+        let surface_texture = match self.acquired.take() {
+            Some(surface_texture) => surface_texture,
+            None => self.get_current_texture()?,
+        };
+        self.acquired.set(Some(surface_texture));
+        Ok(())
+    }
+
+    /// Replaces the surface with a new one targeting `visual` and configures it like the old one,
+    /// keeping the device and everything created from it.
+    ///
+    /// Some drivers leave the swapchain unusable after rapid resizes, so acquiring keeps failing
+    /// with `Lost` even after [`SurfaceState::acquire`] reconfigured the surface. A new surface
+    /// gets a new swapchain. [`crate::Window`] does so for its own surface and its swapchain
+    /// layers.
+    ///
+    /// # Safety
+    ///
+    /// `visual` must be a valid `IDCompositionVisual` pointer that outlives the state, normally
+    /// the one the state was created with.
+    pub unsafe fn recreate_surface(
+        &mut self,
+        wgpu_instance: &wgpu::Instance,
+        visual: *mut c_void,
+    ) -> Result<(), SurfaceStateError> {
+        let surface = unsafe { create_surface(wgpu_instance, visual)? };
+        // Acquired textures belong to the old swapchain, which is released with its surface.
+        drop(self.acquired.take());
+        self.surface = surface;
+        self.surface.configure(&self.device, &self.surface_config);
+        Ok(())
+    }
+
+    /// Gets the next surface texture, reconfiguring the surface once if it is outdated or lost.
+    fn get_current_texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Err(error @ (wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost)) => {
+                warn!(target: LOG_TARGET, "{error}, reconfiguring the surface");
+                self.surface.configure(&self.device, &self.surface_config);
+                self.surface.get_current_texture()
+            }
+            result => result,
+        }
+    }
+
+    /// Creates a view of `surface_texture` in [`SurfaceState::view_format`].
+    pub fn view(&self, surface_texture: &wgpu::SurfaceTexture) -> wgpu::TextureView {
+        surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor {
+                format: Some(self.view_format),
+                ..Default::default()
+            })
+    }
+
+    /// Presents a texture from [`SurfaceState::acquire`] once the commands drawing to it are
+    /// submitted.
+    pub fn present(&self, surface_texture: wgpu::SurfaceTexture) {
+        PresentStatistics::record(&self.present_statistics, || surface_texture.present());
+    }
+
+    /// The timing of the last presents, `None` before the first one. See [`PresentStatistics`]
+    /// for why these don't come from DXGI.
+    pub fn present_statistics(&self) -> Option<PresentStatistics> {
+        let statistics = self.present_statistics.get();
+        (statistics.present_count > 0).then_some(statistics)
+    }
+
+    /// Acquires the next surface texture, lets `render` encode and submit commands drawing to a
+    /// view of it, or of the intermediate texture composited to it if
+    /// [`SurfaceState::set_offscreen`] is enabled, then presents it.
+    ///
+    /// The frame is skipped if acquiring times out or the surface is still outdated after
+    /// [`SurfaceState::acquire`] reconfigured it, as happens while a window is resized rapidly.
+    /// Running out of memory or losing the surface for good is returned as [`Error::Frame`].
+    ///
+    /// [`Error::Frame`]: crate::Error::Frame
+    pub fn render_with(
+        &self,
+        render: impl FnOnce(&SurfaceState, &wgpu::TextureView),
+    ) -> crate::Result<()> {
+        let surface_texture = match self.acquire() {
+            Err(error @ (wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Outdated)) => {
+                warn!(target: LOG_TARGET, "{error}, skipping the frame");
+                return Ok(());
+            }
+            result => result?,
+        };
+        let texture_view = self.view(&surface_texture);
+
+        let cpu_start = Instant::now();
+        match &self.offscreen {
+            Some(offscreen) => {
+                render(self, offscreen.view());
+                offscreen.composite(&self.device, &self.queue, &texture_view);
+            }
+            None => render(self, &texture_view),
+        }
+
+        if let Some(stats) = &self.stats {
+            stats.record(cpu_start.elapsed(), self.gpu_frame_time_ms());
+            stats.draw(
+                &self.device,
+                &self.queue,
+                &texture_view,
+                &self.uniforms.bind_group,
+            );
+        }
+
+        self.present(surface_texture);
+        Ok(())
+    }
+
+    /// Submits a clear of `view` to [`SurfaceState::clear_color`], and the triangle over it if
+    /// [`SurfaceState::draw_triangle`] is set, or the output of the compute shader if
+    /// [`SurfaceState::set_compute`] is enabled. This is what [`SurfaceState::render`] draws.
+    pub fn draw(&self, view: &wgpu::TextureView) {
+        match &self.compute {
+            Some(compute) => {
+                compute.draw(&self.device, &self.queue, &self.uniforms.bind_group, view)
+            }
+            None => self.encode(view, self.draw_triangle),
+        }
+    }
+
+    fn encode(&self, view: &wgpu::TextureView, draw_triangle: bool) {
+        if let Some(timer) = &self.timer {
+            timer.collect(&self.device);
+        }
+
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+
+        // Create the renderpass which will clear the screen. When multisampling, the samples are
+        // resolved into `view` and only it is stored.
+        let (attachment, resolve_target, store) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(view), wgpu::StoreOp::Discard),
+            None => (view, None, wgpu::StoreOp::Store),
+        };
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: attachment,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store,
+                },
+            })],
+            depth_stencil_attachment: self.depth_view.as_ref().map(|view| {
+                wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }
+            }),
+            timestamp_writes: self.timer.as_ref().map(GpuTimer::timestamp_writes),
+            occlusion_query_set: None,
+        });
+
+        if draw_triangle {
+            renderpass.set_pipeline(&self.pipeline);
+            renderpass.set_bind_group(0, &self.uniforms.bind_group, &[]);
+            renderpass.draw(0..3, 0..1);
+        }
+
+        // End the renderpass.
+        drop(renderpass);
+
+        if let Some(timer) = &self.timer {
+            timer.resolve(&mut encoder);
+        }
+
+        // Submit the command in the queue to execute
+        self.queue.submit([encoder.finish()]);
+
+        if let Some(timer) = &self.timer {
+            timer.map();
+        }
+    }
+}
+
+/// Creates a surface targeting `visual`.
+///
+/// # Safety
+///
+/// `visual` must be a valid `IDCompositionVisual` pointer that outlives the surface.
+unsafe fn create_surface(
+    wgpu_instance: &wgpu::Instance,
+    visual: *mut c_void,
+) -> Result<Surface<'static>, SurfaceStateError> {
+    unsafe {
+        wgpu_instance
+            .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::CompositionVisual(visual))
+            .map_err(SurfaceStateError::SurfaceCreation)
+    }
+}
+
+/// Requests the default adapter, compatible with `surface` if given. Falls back to a software
+/// adapter, WARP on DX12, if no hardware adapter is found, e.g. in VMs and on CI runners.
+pub(crate) async fn request_adapter(
+    wgpu_instance: &wgpu::Instance,
+    surface: Option<&Surface<'_>>,
+    descriptor: &SurfaceStateDescriptor,
+) -> Result<wgpu::Adapter, SurfaceStateError> {
+    if let Some(filter) = &descriptor.adapter_name_filter {
+        return find_adapter(wgpu_instance, surface, filter);
+    }
+
+    let request = |force_fallback_adapter| {
+        wgpu_instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter,
+            compatible_surface: surface,
+        })
+    };
+
+    if let Some(adapter) = request(false).await {
+        return Ok(adapter);
+    }
+
+    warn!(
+        target: LOG_TARGET,
+        "no hardware adapter found, falling back to a software adapter (WARP)"
+    );
+    request(true).await.ok_or(SurfaceStateError::NoAdapter)
+}
+
+/// Returns the first DX12 adapter compatible with `surface` whose name contains `filter`. Only DX12
+/// creates surfaces from composition visuals.
+fn find_adapter(
+    wgpu_instance: &wgpu::Instance,
+    surface: Option<&Surface<'_>>,
+    filter: &str,
+) -> Result<wgpu::Adapter, SurfaceStateError> {
+    let needle = filter.to_lowercase();
+    let mut available = Vec::new();
+    for adapter in wgpu_instance.enumerate_adapters(wgpu::Backends::DX12) {
+        if surface.is_some_and(|surface| !adapter.is_surface_supported(surface)) {
+            continue;
+        }
+
+        let info = adapter.get_info();
+        if info.name.to_lowercase().contains(&needle) {
+            info!(
+                target: LOG_TARGET,
+                "adapter {} ({:?}) matches {filter:?}", info.name, info.backend
+            );
+            return Ok(adapter);
+        }
+        available.push(format!("{} ({:?})", info.name, info.backend));
+    }
+
+    Err(SurfaceStateError::NoMatchingAdapter {
+        filter: filter.to_owned(),
+        available,
+    })
+}
+
+/// Requests a device with the features and limits of `descriptor` from `adapter`, and timestamp
+/// queries if [`SurfaceStateDescriptor::gpu_timing`] is enabled and the adapter supports them.
+pub(crate) async fn request_device(
+    adapter: &wgpu::Adapter,
+    descriptor: &SurfaceStateDescriptor,
+) -> Result<(Device, Queue), SurfaceStateError> {
+    let missing_features = descriptor.required_features - adapter.features();
+    if !missing_features.is_empty() {
+        return Err(SurfaceStateError::UnsupportedFeatures(missing_features));
+    }
+
+    let mut missing_limits = Vec::new();
+    descriptor
+        .required_limits
+        .check_limits_with_fail_fn(&adapter.limits(), false, |name, _, _| {
+            missing_limits.push(name)
+        });
+    if !missing_limits.is_empty() {
+        return Err(SurfaceStateError::UnsupportedLimits(missing_limits));
+    }
+
+    let mut required_features = descriptor.required_features;
+    if descriptor.gpu_timing {
+        required_features |= adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
+    }
+
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features,
+                required_limits: descriptor.required_limits.clone(),
+                memory_hints: Default::default(),
+            },
+            None,
+        )
+        .await
+        .map_err(SurfaceStateError::NoDevice)
+}
+
+/// Creates a depth buffer the size of the surface configured with `config`.
+fn create_depth_view(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&Default::default())
+}
+
+/// Creates the multisampled color attachment, a texture of the surface's format viewed as
+/// `view_format`, so resolving into a `view_format` view of the frame averages in the same space.
+fn create_msaa_view(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    view_format: TextureFormat,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("msaa"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: if view_format == config.format {
+            &[]
+        } else {
+            std::slice::from_ref(&view_format)
+        },
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        format: Some(view_format),
+        ..Default::default()
+    })
+}
+
+/// Returns `requested` if all of `formats` can be multisampled that many times on `device`, or 1.
+fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    device: &Device,
+    formats: [Option<TextureFormat>; 3],
+    requested: u32,
+) -> u32 {
+    if requested <= 1 {
+        return 1;
+    }
+    // Without this feature, wgpu only allows the counts every adapter supports.
+    let adapter_specific = device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let supported = formats.into_iter().flatten().all(|format| {
+        let features = if adapter_specific {
+            adapter.get_texture_format_features(format)
+        } else {
+            format.guaranteed_format_features(device.features())
+        };
+        features.flags.sample_count_supported(requested)
+    });
+    if !supported {
+        warn!(
+            target: LOG_TARGET,
+            "{requested} samples per pixel are not supported, multisampling is disabled"
+        );
+        return 1;
+    }
+    requested
+}
+
+/// The format to view a `format` surface with: its sRGB variant if it has one, e.g.
+/// `Bgra8UnormSrgb` for both `Bgra8Unorm` and `Bgra8UnormSrgb`, or `format` itself otherwise.
+pub(crate) fn view_format(format: TextureFormat) -> TextureFormat {
+    format.add_srgb_suffix()
+}
+
+/// Returns the first of `preferred` in `supported`, or the first of `supported` if there is none.
+fn select_format(
+    preferred: &[TextureFormat],
+    supported: &[TextureFormat],
+) -> Option<TextureFormat> {
+    if let Some(format) = preferred.iter().find(|format| supported.contains(format)) {
+        return Some(*format);
+    }
+
+    let fallback = *supported.first()?;
+    warn!(
+        target: LOG_TARGET,
+        "surface supports none of the formats {preferred:?}, falling back to {fallback:?}"
+    );
+    Some(fallback)
+}
+
+/// Surface capabilities formatted as a table, one property per line.
+struct CapabilitiesTable<'a>(&'a wgpu::SurfaceCapabilities);
+
+impl fmt::Display for CapabilitiesTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: fmt::Debug>(values: &[T]) -> String {
+            if values.is_empty() {
+                return "none".to_owned();
+            }
+            values
+                .iter()
+                .map(|value| format!("{value:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+
+        let capabilities = self.0;
+        writeln!(f, "surface capabilities:")?;
+        writeln!(f, "  formats:       {}", join(&capabilities.formats))?;
+        writeln!(f, "  present modes: {}", join(&capabilities.present_modes))?;
+        writeln!(f, "  alpha modes:   {}", join(&capabilities.alpha_modes))?;
+        write!(f, "  usages:        {:?}", capabilities.usages)
+    }
+}
+
+/// Returns `requested` if `supported` allows it, or `Fifo`, which every surface supports.
+fn select_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    // The automatic modes are resolved by wgpu and aren't listed in the capabilities.
+    if matches!(
+        requested,
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+    ) || supported.contains(&requested)
+    {
+        return requested;
+    }
+
+    // Without vsync, either mode is closer to the request than waiting for vblanks.
+    let fallback = [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox]
+        .into_iter()
+        .filter(|_| {
+            matches!(
+                requested,
+                wgpu::PresentMode::Immediate | wgpu::PresentMode::Mailbox
+            )
+        })
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo);
+    warn!(
+        target: LOG_TARGET,
+        "present mode {requested:?} is not supported, falling back to {fallback:?}"
+    );
+    fallback
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn views_bgra_surfaces_as_srgb() {
+        assert_eq!(
+            view_format(TextureFormat::Bgra8Unorm),
+            TextureFormat::Bgra8UnormSrgb
+        );
+        assert_eq!(
+            view_format(TextureFormat::Bgra8UnormSrgb),
+            TextureFormat::Bgra8UnormSrgb
+        );
+    }
+
+    #[test]
+    fn views_formats_without_srgb_variant_as_is() {
+        assert_eq!(
+            view_format(TextureFormat::Rgba16Float),
+            TextureFormat::Rgba16Float
+        );
+    }
+}
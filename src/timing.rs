@@ -0,0 +1,304 @@
+use std::{
+    cell::Cell,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use log::debug;
+use wgpu::{Buffer, CommandEncoder, Device, QuerySet, Queue};
+use windows::Win32::{
+    Graphics::DirectComposition::DCOMPOSITION_FRAME_STATISTICS,
+    System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency},
+};
+
+use crate::LOG_TARGET;
+
+/// How often the average GPU frame time is logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The compositor's timing around the last frame it composed, from
+/// `IDCompositionDevice2::GetFrameStatistics`.
+///
+/// Times are `QueryPerformanceCounter` ticks, comparable with other QPC timestamps such as DXGI
+/// present statistics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompositionStatistics {
+    /// When the last frame was composed.
+    pub last_frame_time: i64,
+    /// When the next frame is estimated to be composed.
+    pub next_estimated_frame_time: i64,
+    /// When the statistics were queried.
+    pub current_time: i64,
+    /// QPC ticks per second.
+    pub time_frequency: i64,
+    /// The rate frames are composed at, in Hz.
+    pub composition_rate: f64,
+}
+
+impl CompositionStatistics {
+    /// The time between composed frames, zero if the compositor reports no rate.
+    pub fn refresh_interval(&self) -> Duration {
+        if self.composition_rate > 0.0 {
+            Duration::from_secs_f64(1.0 / self.composition_rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// The time from the last composed frame to the query.
+    pub fn since_last_frame(&self) -> Duration {
+        self.ticks_to_duration(self.current_time - self.last_frame_time)
+    }
+
+    /// The time from the query to the next estimated frame.
+    pub fn until_next_frame(&self) -> Duration {
+        self.ticks_to_duration(self.next_estimated_frame_time - self.current_time)
+    }
+
+    /// Negative spans are clamped to zero.
+    fn ticks_to_duration(&self, ticks: i64) -> Duration {
+        if ticks <= 0 || self.time_frequency <= 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(ticks as f64 / self.time_frequency as f64)
+    }
+}
+
+impl From<DCOMPOSITION_FRAME_STATISTICS> for CompositionStatistics {
+    fn from(statistics: DCOMPOSITION_FRAME_STATISTICS) -> Self {
+        let rate = statistics.currentCompositionRate;
+        Self {
+            last_frame_time: statistics.lastFrameTime,
+            next_estimated_frame_time: statistics.nextEstimatedFrameTime,
+            current_time: statistics.currentTime,
+            time_frequency: statistics.timeFrequency,
+            composition_rate: if rate.Denominator == 0 {
+                0.0
+            } else {
+                rate.Numerator as f64 / rate.Denominator as f64
+            },
+        }
+    }
+}
+
+/// The timing of the last presents of a [`crate::SurfaceState`], from
+/// [`crate::SurfaceState::present_statistics`].
+///
+/// DXGI's `GetFrameStatistics` would tell when presents reached the screen, but wgpu doesn't
+/// expose the swapchain it creates for the visual. These are measured around the present calls
+/// instead, in `QueryPerformanceCounter` ticks like [`CompositionStatistics`]: the present
+/// cadence compared with the composition cadence tells queueing in wgpu from the compositor's
+/// latency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PresentStatistics {
+    /// The number of frames presented since the surface was created.
+    pub present_count: u64,
+    /// When the last present was called.
+    pub last_present_start: i64,
+    /// When the last present returned, later than the start if it blocked, e.g. with
+    /// [`wgpu::PresentMode::Fifo`] and a full queue.
+    pub last_present_end: i64,
+    /// When the present before the last one returned, zero before the second present.
+    pub previous_present_end: i64,
+    /// QPC ticks per second.
+    pub time_frequency: i64,
+}
+
+impl PresentStatistics {
+    /// How long the last present call blocked.
+    pub fn present_duration(&self) -> Duration {
+        self.ticks_to_duration(self.last_present_end - self.last_present_start)
+    }
+
+    /// The time between the last two presents, zero before the second one.
+    pub fn present_interval(&self) -> Duration {
+        if self.previous_present_end == 0 {
+            return Duration::ZERO;
+        }
+        self.ticks_to_duration(self.last_present_end - self.previous_present_end)
+    }
+
+    /// Negative spans are clamped to zero.
+    fn ticks_to_duration(&self, ticks: i64) -> Duration {
+        if ticks <= 0 || self.time_frequency <= 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(ticks as f64 / self.time_frequency as f64)
+    }
+
+    /// Runs `present` and records its timing.
+    pub(crate) fn record(statistics: &Cell<Self>, present: impl FnOnce()) {
+        let start = query_performance_counter();
+        present();
+        let end = query_performance_counter();
+
+        let last = statistics.get();
+        let mut time_frequency = last.time_frequency;
+        if time_frequency == 0 {
+            // Fixed at boot, only queried once.
+            unsafe {
+                let _ = QueryPerformanceFrequency(&mut time_frequency);
+            }
+        }
+        statistics.set(Self {
+            present_count: last.present_count + 1,
+            last_present_start: start,
+            last_present_end: end,
+            previous_present_end: last.last_present_end,
+            time_frequency,
+        });
+    }
+}
+
+fn query_performance_counter() -> i64 {
+    let mut ticks = 0;
+    unsafe {
+        let _ = QueryPerformanceCounter(&mut ticks);
+    }
+    ticks
+}
+
+/// Measures the GPU duration of render passes with a pair of timestamp queries.
+///
+/// Results are read back without blocking: the readback buffer is mapped after the frame is
+/// submitted and read during a later frame, frames rendered while it is in flight aren't measured.
+pub(crate) struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+    /// Whether the last resolve was copied into `readback_buffer`, which should now be mapped.
+    copied: Cell<bool>,
+    /// Receives the result of mapping `readback_buffer`, which can't be copied into meanwhile.
+    in_flight: Cell<Option<Receiver<Result<(), wgpu::BufferAsyncError>>>>,
+    last_frame_ms: Cell<Option<f64>>,
+    /// The start, total milliseconds and frame count of the current report interval.
+    interval: Cell<(Instant, f64, u32)>,
+}
+
+impl GpuTimer {
+    /// Requires [`wgpu::Features::TIMESTAMP_QUERY`] on `device`.
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let size = 2 * wgpu::QUERY_SIZE as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("frame timing"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("frame timing resolve"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("frame timing readback"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period() as f64,
+            copied: Cell::new(false),
+            in_flight: Cell::new(None),
+            last_frame_ms: Cell::new(None),
+            interval: Cell::new((Instant::now(), 0.0, 0)),
+        }
+    }
+
+    /// The GPU duration of the last measured render pass, in milliseconds.
+    pub fn last_frame_ms(&self) -> Option<f64> {
+        self.last_frame_ms.get()
+    }
+
+    /// Timestamp writes at the beginning and end of a render pass.
+    pub fn timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    /// Resolves the timestamps written by the pass encoded into `encoder`, and copies them for
+    /// readback unless a previous frame's are still in flight.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        if !self.is_in_flight() {
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                self.resolve_buffer.size(),
+            );
+            self.copied.set(true);
+        }
+    }
+
+    /// Starts mapping the timestamps copied by [`GpuTimer::resolve`], once the commands encoded
+    /// with it have been submitted.
+    pub fn map(&self) {
+        if !self.copied.replace(false) {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.in_flight.set(Some(receiver));
+    }
+
+    fn is_in_flight(&self) -> bool {
+        let receiver = self.in_flight.take();
+        let in_flight = receiver.is_some();
+        self.in_flight.set(receiver);
+        in_flight
+    }
+
+    /// Reads the timestamps of a previous frame if they have been mapped.
+    pub fn collect(&self, device: &Device) {
+        let Some(receiver) = self.in_flight.take() else {
+            return;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {}
+            // A failed mapping is dropped, the next frame is measured instead.
+            Ok(Err(_)) | Err(TryRecvError::Disconnected) => return,
+            Err(TryRecvError::Empty) => {
+                self.in_flight.set(Some(receiver));
+                return;
+            }
+        }
+
+        let timestamps = {
+            let range = self.readback_buffer.slice(..).get_mapped_range();
+            let timestamp = |i: usize| u64::from_ne_bytes(range[i * 8..][..8].try_into().unwrap());
+            (timestamp(0), timestamp(1))
+        };
+        self.readback_buffer.unmap();
+
+        let ticks = timestamps.1.wrapping_sub(timestamps.0);
+        let frame_ms = ticks as f64 * self.period / 1_000_000.0;
+        self.last_frame_ms.set(Some(frame_ms));
+
+        let (start, total_ms, frames) = self.interval.get();
+        let (total_ms, frames) = (total_ms + frame_ms, frames + 1);
+        if start.elapsed() < REPORT_INTERVAL {
+            self.interval.set((start, total_ms, frames));
+            return;
+        }
+
+        debug!(
+            target: LOG_TARGET,
+            "GPU frame time: {:.3} ms",
+            total_ms / frames as f64
+        );
+        self.interval.set((Instant::now(), 0.0, 0));
+    }
+}
@@ -0,0 +1,91 @@
+use wgpu::{BindGroup, BindGroupLayout, Buffer, Device};
+
+/// Values [`crate::SurfaceState`] uploads before each frame, bound at group 0, binding 0 of its
+/// pipelines. Shaders can declare them as:
+///
+/// ```wgsl
+/// struct Uniforms {
+///     time: f32,
+///     resolution: vec2<f32>,
+///     mouse: vec4<f32>,
+/// }
+///
+/// @group(0) @binding(0) var<uniform> uniforms: Uniforms;
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Uniforms {
+    /// Seconds since the surface was created.
+    pub time: f32,
+    /// The surface size in physical pixels.
+    pub resolution: [f32; 2],
+    /// The mouse like Shadertoy's `iMouse`, in surface pixels from the bottom left: where it was
+    /// last dragged with the left button held in `xy`, and where the button was pressed in `zw`,
+    /// negated once it is released. See [`crate::SurfaceState::set_mouse`].
+    pub mouse: [f32; 4],
+}
+
+impl Uniforms {
+    /// The size of the WGSL struct, `resolution` is aligned to 8 bytes and `mouse` to 16.
+    pub const SIZE: u64 = 32;
+
+    /// The uniforms laid out like the WGSL struct.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE as usize] {
+        let mut bytes = [0; Self::SIZE as usize];
+        bytes[0..4].copy_from_slice(&self.time.to_ne_bytes());
+        bytes[8..12].copy_from_slice(&self.resolution[0].to_ne_bytes());
+        bytes[12..16].copy_from_slice(&self.resolution[1].to_ne_bytes());
+        for (i, value) in self.mouse.iter().enumerate() {
+            bytes[16 + i * 4..][..4].copy_from_slice(&value.to_ne_bytes());
+        }
+        bytes
+    }
+}
+
+/// The buffer holding [`Uniforms`] and a bind group for it.
+pub(crate) struct UniformBinding {
+    pub layout: BindGroupLayout,
+    pub buffer: Buffer,
+    pub bind_group: BindGroup,
+}
+
+impl UniformBinding {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("uniforms"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX
+                    | wgpu::ShaderStages::FRAGMENT
+                    | wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniforms"),
+            size: Uniforms::SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("uniforms"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            layout,
+            buffer,
+            bind_group,
+        }
+    }
+}
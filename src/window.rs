@@ -0,0 +1,3245 @@
+use std::{
+    cell::Cell,
+    ffi::c_void,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, trace, warn};
+use windows::{
+    core::*,
+    Foundation::Numerics::{Matrix3x2, Matrix4x4},
+    Win32::{
+        Foundation::*,
+        Graphics::{
+            Direct2D::{
+                Common::{D2D_MATRIX_4X4_F, D2D_MATRIX_4X4_F_0, D2D_RECT_F},
+                ID2D1DeviceContext,
+            },
+            Direct3D::*,
+            Direct3D11::*,
+            DirectComposition::*,
+            Dxgi::*,
+            Gdi::*,
+        },
+        System::{LibraryLoader::*, Threading::*},
+        UI::{HiDpi::*, Input::KeyboardAndMouse::VK_ESCAPE, WindowsAndMessaging::*},
+    },
+};
+
+use crate::{
+    create_device_2d, create_device_3d, hiword,
+    input::Utf16Decoder,
+    layer::{Layer, LayerContent, LayerKind},
+    loword,
+    shader::{watch_shader, WM_SHADER_CHANGED},
+    AlphaMask, CompositionStatistics, CompositionSurface, Error, HitTest, KeyEvent, LayerId,
+    MouseButton, MouseButtons, MouseEvent, MouseMotion, Result, ScrollEvent, SharedDevice,
+    SurfaceState, SurfaceStateDescriptor, DEFAULT_CLEAR_COLOR, LOG_TARGET,
+};
+
+/// How [`Window::run`] waits for messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Block until a message arrives and only render when the window is invalidated, e.g. by
+    /// [`Window::request_redraw`].
+    #[default]
+    Wait,
+    /// Process pending messages without blocking and render after every iteration, for
+    /// continuous animation.
+    Poll,
+    /// Like [`LoopMode::Poll`], but wait for the next tick of the compositor clock after
+    /// rendering, so frames are produced at the rate DComp composes them. See
+    /// [`Window::run_vsynced`].
+    CompositorClock,
+    /// Like [`LoopMode::Poll`], but wait until the swapchain accepts another frame before
+    /// processing messages, so frames render from the latest input. See
+    /// [`Window::run_low_latency`].
+    LowLatency,
+}
+
+/// Configures the title, geometry and style of a [`Window`].
+///
+/// A builder can be reused to create several windows.
+#[derive(Clone, Debug)]
+pub struct WindowBuilder {
+    title: String,
+    /// RGBA pixels, width and height.
+    icon: Option<(Vec<u8>, u32, u32)>,
+    size: Option<(u32, u32)>,
+    min_size: Option<(u32, u32)>,
+    max_size: Option<(u32, u32)>,
+    position: Option<(i32, i32)>,
+    monitor: Option<usize>,
+    resizable: bool,
+    decorations: bool,
+    shader: Option<PathBuf>,
+    /// Whether `shader` is a Shadertoy-style fragment shader.
+    shadertoy: bool,
+    surface_descriptor: SurfaceStateDescriptor,
+    backends: wgpu::Backends,
+    shared_device: Option<SharedDevice>,
+    loop_mode: LoopMode,
+    render_scale: f32,
+    d3d_driver_type: D3D_DRIVER_TYPE,
+    d3d_debug: bool,
+}
+
+impl Default for WindowBuilder {
+    fn default() -> Self {
+        Self {
+            title: "Sample Window".to_owned(),
+            icon: None,
+            size: None,
+            min_size: None,
+            max_size: None,
+            position: None,
+            monitor: None,
+            resizable: true,
+            decorations: true,
+            shader: None,
+            shadertoy: false,
+            surface_descriptor: SurfaceStateDescriptor::default(),
+            backends: wgpu::Backends::all(),
+            shared_device: None,
+            loop_mode: LoopMode::default(),
+            render_scale: 1.0,
+            d3d_driver_type: D3D_DRIVER_TYPE_HARDWARE,
+            d3d_debug: false,
+        }
+    }
+}
+
+impl WindowBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_owned();
+        self
+    }
+
+    /// Sets the icon of the window's title bar and taskbar button from `width` x `height` RGBA
+    /// pixels with straight alpha, rows top to bottom. Building fails unless `rgba` holds exactly
+    /// that many pixels.
+    pub fn icon_from_rgba(mut self, rgba: &[u8], width: u32, height: u32) -> Self {
+        self.icon = Some((rgba.to_vec(), width, height));
+        self
+    }
+
+    /// Sets the initial client area size. Defaults to a size chosen by the system.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// Sets the smallest client area size the user can resize the window to, see
+    /// [`Window::set_min_size`].
+    pub fn min_size(mut self, width: u32, height: u32) -> Self {
+        self.min_size = Some((width, height));
+        self
+    }
+
+    /// Sets the largest client area size the user can resize or maximize the window to, see
+    /// [`Window::set_max_size`].
+    pub fn max_size(mut self, width: u32, height: u32) -> Self {
+        self.max_size = Some((width, height));
+        self
+    }
+
+    /// Sets the initial position of the window's top-left corner in screen coordinates. Defaults
+    /// to a position chosen by the system.
+    pub fn position(mut self, x: i32, y: i32) -> Self {
+        self.position = Some((x, y));
+        self
+    }
+
+    /// Opens the window on the monitor at `index` in `EnumDisplayMonitors` order. The position,
+    /// if any, becomes relative to the monitor's work area, otherwise the window is centered on
+    /// it, and the frame is sized for the monitor's DPI. Falls back to the default placement if
+    /// there is no such monitor.
+    pub fn monitor(mut self, index: usize) -> Self {
+        self.monitor = Some(index);
+        self
+    }
+
+    /// Whether the window can be resized and maximized by the user. Defaults to `true`.
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    /// Whether the window has a title bar and border. Defaults to `true`.
+    ///
+    /// Without them the client area covers the whole window, which can still be moved through a
+    /// drag region, see [`Window::set_drag_region_height`] and [`Window::on_hit_test`].
+    pub fn decorations(mut self, decorations: bool) -> Self {
+        self.decorations = decorations;
+        self
+    }
+
+    /// Draws with the WGSL shader at `path` (see [`SurfaceState::new_with_shader`]) and reloads
+    /// it whenever the file changes.
+    pub fn shader(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shader = Some(path.into());
+        self.shadertoy = false;
+        self
+    }
+
+    /// Draws with the Shadertoy-style fragment shader at `path` (see
+    /// [`SurfaceState::new_shadertoy`]) and reloads it whenever the file changes. Its `iMouse`
+    /// follows the window's mouse input.
+    pub fn shadertoy(mut self, path: impl Into<PathBuf>) -> Self {
+        self.shader = Some(path.into());
+        self.shadertoy = true;
+        self
+    }
+
+    /// Sets the options used to create the window's [`SurfaceState`].
+    pub fn surface_descriptor(mut self, descriptor: SurfaceStateDescriptor) -> Self {
+        self.surface_descriptor = descriptor;
+        self
+    }
+
+    /// Restricts the wgpu backends the surface may be created with, e.g. [`wgpu::Backends::DX12`].
+    /// Defaults to all backends.
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    /// Renders with the devices of `shared` instead of creating a D3D11 device and a wgpu device
+    /// for this window, so several windows can share them. [`WindowBuilder::backends`] is ignored,
+    /// the instance of `shared` is used.
+    pub fn shared_device(mut self, shared: &SharedDevice) -> Self {
+        self.shared_device = Some(shared.clone());
+        self
+    }
+
+    /// Sets how the window's message loop waits for messages. Defaults to [`LoopMode::Wait`].
+    pub fn loop_mode(mut self, loop_mode: LoopMode) -> Self {
+        self.loop_mode = loop_mode;
+        self
+    }
+
+    /// Sets the driver type of the D3D11 device behind the composition, e.g.
+    /// `D3D_DRIVER_TYPE_WARP` to rule out the GPU driver. Defaults to `D3D_DRIVER_TYPE_HARDWARE`.
+    /// Ignored with [`WindowBuilder::shared_device`].
+    pub fn d3d_driver_type(mut self, driver_type: D3D_DRIVER_TYPE) -> Self {
+        self.d3d_driver_type = driver_type;
+        self
+    }
+
+    /// Enables the D3D11 debug layer if it is installed, see [`crate::create_device_3d`].
+    /// Defaults to `false`. Ignored with [`WindowBuilder::shared_device`].
+    pub fn d3d_debug(mut self, debug: bool) -> Self {
+        self.d3d_debug = debug;
+        self
+    }
+
+    /// Renders at a fraction of the client size, see [`Window::set_render_scale`]. Defaults to
+    /// `1.0`.
+    pub fn render_scale(mut self, render_scale: f32) -> Self {
+        self.render_scale = render_scale;
+        self
+    }
+
+    pub fn build(&self) -> Result<Window> {
+        if self.title.contains('\0') {
+            return Err(windows::core::Error::from(E_INVALIDARG).into());
+        }
+        if let Some((rgba, width, height)) = &self.icon {
+            if *width == 0 || *height == 0 || rgba.len() != *width as usize * *height as usize * 4 {
+                return Err(windows::core::Error::from(E_INVALIDARG).into());
+            }
+        }
+        let title = HSTRING::from(self.title.as_str());
+        let wgpu = match &self.shared_device {
+            Some(shared) => shared.instance.clone(),
+            None => wgpu::Instance::new(&wgpu::InstanceDescriptor {
+                backends: self.backends,
+                ..Default::default()
+            }),
+        };
+
+        Ok(Window {
+            hwnd: Default::default(),
+            device: None,
+            desktop: None,
+            target: None,
+            root_visual: None,
+            wgpu_visual: None,
+            transform: Matrix3x2::identity(),
+            transform_3d: None,
+            backface_visible: true,
+            depth_mode: DCOMPOSITION_DEPTH_MODE_TREE,
+            clip: None,
+            clip_radius: 0.0,
+            backdrop_visual: None,
+            backdrop_blur: None,
+            wgpu_instance: wgpu,
+            shared_device: self.shared_device.clone(),
+            wgpu_state: None,
+            draw_triangle: self.shader.is_some(),
+            clear_color: DEFAULT_CLEAR_COLOR,
+            show_stats: false,
+            offscreen: false,
+            compute: false,
+            simulate_device_removed: false,
+            scale_factor: 1.0,
+            client_size: None,
+            mouse_handler: None,
+            key_handler: Some(Box::new(close_on_escape)),
+            scroll_handler: None,
+            char_handler: None,
+            motion_handler: None,
+            utf16: Utf16Decoder::default(),
+            close_handler: None,
+            hit_test_handler: None,
+            alpha_hit_test: None,
+            alpha_mask: None,
+            alpha_hit_test_handler: None,
+            passing_through: false,
+            drag_region_height: 0,
+            resize_border: 8,
+            render_handler: Box::new(|state, view| state.draw(view)),
+            layers: Vec::new(),
+            layer_order: Vec::new(),
+            loop_mode: self.loop_mode,
+            frame_rate_limit: None,
+            present_pacing: true,
+            next_frame: None,
+            render_scale: self.render_scale,
+            d3d_driver_type: self.d3d_driver_type,
+            d3d_debug: self.d3d_debug,
+            feature_level: None,
+            focused: false,
+            pause_when_inactive: true,
+            needs_redraw: Cell::new(true),
+            needs_commit: Cell::new(false),
+            title,
+            icon_rgba: self.icon.clone(),
+            icon: None,
+            style: self.style(),
+            decorations: self.decorations,
+            windowed: None,
+            topmost: false,
+            click_through: false,
+            position: self.position,
+            monitor: self.monitor,
+            size: self.size,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            shader: self.shader.clone(),
+            shadertoy: self.shadertoy,
+            mouse: [0.0; 4],
+            surface_descriptor: self.surface_descriptor.clone(),
+        })
+    }
+
+    fn style(&self) -> WINDOW_STYLE {
+        // Borderless windows keep the sizing frame when resizable, it is hidden by
+        // `WM_NCCALCSIZE` but still lets the window be resized.
+        let style = if self.decorations {
+            WS_OVERLAPPED | WS_CAPTION | WS_SYSMENU | WS_MINIMIZEBOX | WS_VISIBLE
+        } else {
+            WS_POPUP | WS_SYSMENU | WS_MINIMIZEBOX | WS_VISIBLE
+        };
+        if self.resizable {
+            style | WS_SIZEBOX | WS_MAXIMIZEBOX
+        } else {
+            style
+        }
+    }
+}
+
+/// A Win32 window whose client area is composited by DirectComposition and rendered with wgpu.
+pub struct Window {
+    hwnd: HWND,
+    device: Option<ID3D11Device>,
+    desktop: Option<IDCompositionDesktopDevice>,
+    target: Option<IDCompositionTarget>,
+    root_visual: Option<IDCompositionVisual2>,
+    wgpu_visual: Option<IDCompositionVisual2>,
+    transform: Matrix3x2,
+    /// Replaces `transform` while set, see [`Window::set_transform_3d`].
+    transform_3d: Option<Matrix4x4>,
+    backface_visible: bool,
+    depth_mode: DCOMPOSITION_DEPTH_MODE,
+    clip: Option<D2D_RECT_F>,
+    clip_radius: f32,
+    backdrop_visual: Option<IDCompositionVisual2>,
+    backdrop_blur: Option<f32>,
+    wgpu_instance: wgpu::Instance,
+    shared_device: Option<SharedDevice>,
+    wgpu_state: Option<SurfaceState>,
+    draw_triangle: bool,
+    clear_color: wgpu::Color,
+    show_stats: bool,
+    offscreen: bool,
+    compute: bool,
+    simulate_device_removed: bool,
+    scale_factor: f64,
+    client_size: Option<(u32, u32)>,
+    title: HSTRING,
+    icon_rgba: Option<(Vec<u8>, u32, u32)>,
+    /// Created from `icon_rgba` along with the window, destroyed with it.
+    icon: Option<HICON>,
+    style: WINDOW_STYLE,
+    decorations: bool,
+    /// The style and window rect to restore when leaving fullscreen.
+    windowed: Option<(WINDOW_STYLE, RECT)>,
+    topmost: bool,
+    click_through: bool,
+    position: Option<(i32, i32)>,
+    monitor: Option<usize>,
+    size: Option<(u32, u32)>,
+    min_size: Option<(u32, u32)>,
+    max_size: Option<(u32, u32)>,
+    shader: Option<PathBuf>,
+    shadertoy: bool,
+    /// The [`crate::Uniforms::mouse`] of the window's surface, see [`Window::track_mouse`].
+    mouse: [f32; 4],
+    pub(crate) surface_descriptor: SurfaceStateDescriptor,
+    mouse_handler: Option<Handler<MouseEvent>>,
+    key_handler: Option<Handler<KeyEvent>>,
+    scroll_handler: Option<Handler<ScrollEvent>>,
+    char_handler: Option<Handler<char>>,
+    motion_handler: Option<Handler<MouseMotion>>,
+    utf16: Utf16Decoder,
+    close_handler: Option<CloseHandler>,
+    hit_test_handler: Option<HitTestHandler>,
+    /// The alpha up to which pixels let mouse input through, see [`Window::set_alpha_hit_test`].
+    alpha_hit_test: Option<u8>,
+    /// The mask read back after the last frame while `alpha_hit_test` is set.
+    alpha_mask: Option<AlphaMask>,
+    alpha_hit_test_handler: Option<AlphaHitTestHandler>,
+    /// Whether the window is click-through because the cursor is over a transparent pixel, see
+    /// [`Window::start_passing_through`].
+    passing_through: bool,
+    /// The height of the strip along the top of the client area dragging the window, in logical
+    /// pixels.
+    drag_region_height: u32,
+    /// The thickness of the resize edges of borderless windows, in logical pixels.
+    resize_border: u32,
+    pub(crate) render_handler: RenderHandler,
+    layers: Vec<Layer>,
+    /// The stacking order of the layers, bottom first, see [`Window::set_layer_order`].
+    layer_order: Vec<LayerId>,
+    loop_mode: LoopMode,
+    /// The maximum frames per second rendered continuously, see
+    /// [`Window::set_frame_rate_limit`].
+    frame_rate_limit: Option<u32>,
+    /// Whether uncapped non-blocking presents are paced, see [`Window::set_present_pacing`].
+    present_pacing: bool,
+    /// When the next frame is due under the frame rate limit or present pacing.
+    next_frame: Option<Instant>,
+    render_scale: f32,
+    d3d_driver_type: D3D_DRIVER_TYPE,
+    d3d_debug: bool,
+    feature_level: Option<D3D_FEATURE_LEVEL>,
+    focused: bool,
+    pause_when_inactive: bool,
+    /// Whether the next paint renders a frame, see [`Window::request_redraw`].
+    needs_redraw: Cell<bool>,
+    /// Whether visual properties changed since the last commit, see [`Window::commit`].
+    needs_commit: Cell<bool>,
+}
+
+/// A user callback for input events, given mutable access to the window.
+type Handler<E> = Box<dyn FnMut(&mut Window, E)>;
+
+/// A user callback deciding whether the window may close.
+type CloseHandler = Box<dyn FnMut(&mut Window) -> bool>;
+
+/// A user callback classifying a point in client coordinates for `WM_NCHITTEST`.
+type HitTestHandler = Box<dyn FnMut(&Window, (i32, i32)) -> Option<HitTest>>;
+
+/// A user callback deciding whether a point in client coordinates captures mouse input.
+type AlphaHitTestHandler = Box<dyn FnMut(&Window, (i32, i32)) -> bool>;
+
+/// The timer polling the cursor while the window lets mouse input through transparent pixels.
+const PASS_THROUGH_TIMER: usize = 1;
+
+/// How often the cursor is polled while passing through, in milliseconds.
+const PASS_THROUGH_POLL_INTERVAL: u32 = 50;
+
+/// A user callback encoding a frame into the acquired surface texture's view.
+pub(crate) type RenderHandler = Box<dyn FnMut(&SurfaceState, &wgpu::TextureView)>;
+
+impl Window {
+    /// Creates a window with the default [`WindowBuilder`] settings. The Win32 window itself is
+    /// created by [`Window::run`].
+    pub fn new() -> Result<Self> {
+        WindowBuilder::new().build()
+    }
+
+    /// The window handle, or an invalid handle before [`Window::run`] has created the window.
+    pub fn hwnd(&self) -> HWND {
+        self.hwnd
+    }
+
+    /// The D3D11 device backing the composition, once the first paint has created it, for
+    /// sharing textures between D3D11 rendering and the visuals of the window, e.g. through
+    /// [`Window::desktop_device`].
+    ///
+    /// The device is released with the window's other device resources after device loss, when
+    /// the window closes or changes like [`Window::set_transparent`] need new resources, and the
+    /// next paint creates a new one, or takes the one of the [`SharedDevice`] the window was built
+    /// with. A clone keeps the released device alive, but it no longer backs the composition, so
+    /// resources created from it have to be recreated from the new device. This returns `None`
+    /// until it exists.
+    ///
+    /// The device can create resources from any thread, but its immediate context isn't thread
+    /// safe and the window uses it to update composition surfaces on the window's thread. Use the
+    /// immediate context on that thread only, or deferred contexts elsewhere.
+    pub fn d3d11_device(&self) -> Option<&ID3D11Device> {
+        self.device.as_ref()
+    }
+
+    /// The DComp desktop device, once the first paint has created it, for extending the visual
+    /// tree with visuals and effects of your own. It is released and recreated along with
+    /// [`Window::d3d11_device`], taking the visual tree with it, so anything added has to be
+    /// added again to the new tree.
+    ///
+    /// Changes only show once committed: call [`Window::schedule_commit`] to have them committed
+    /// with the next frame, followed by [`Window::commit`] to commit them right away.
+    pub fn desktop_device(&self) -> Option<&IDCompositionDesktopDevice> {
+        self.desktop.as_ref()
+    }
+
+    /// The root of the window's visual tree, once the first paint has created it. Its children
+    /// are the wgpu visual, above [`Window::backdrop_visual`] if any, and the visuals of the
+    /// layers stacked above it, see [`Window::set_layer_order`]. Visuals added to it should be
+    /// inserted relative to those, and committed like changes through
+    /// [`Window::desktop_device`].
+    pub fn root_visual(&self) -> Option<&IDCompositionVisual2> {
+        self.root_visual.as_ref()
+    }
+
+    /// The visual the window's surface renders to, once the first paint has created it. The
+    /// window sets its transform and clip, e.g. with [`Window::set_transform`] or
+    /// [`Window::set_rounded_clip`], overwriting changes made to those properties directly.
+    pub fn wgpu_visual(&self) -> Option<&IDCompositionVisual2> {
+        self.wgpu_visual.as_ref()
+    }
+
+    /// The feature level of the D3D11 device, once the first paint has created it.
+    pub fn feature_level(&self) -> Option<D3D_FEATURE_LEVEL> {
+        self.feature_level
+    }
+
+    /// The ratio of the window's current DPI to the default 96 DPI.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Sets the callback invoked for mouse events in the client area.
+    pub fn on_mouse(&mut self, handler: impl FnMut(&mut Window, MouseEvent) + 'static) {
+        self.mouse_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback invoked for vertical and horizontal mouse wheel rotation while the
+    /// window has focus.
+    pub fn on_scroll(&mut self, handler: impl FnMut(&mut Window, ScrollEvent) + 'static) {
+        self.scroll_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback invoked for each character typed, decoded from `WM_CHAR`. Unlike
+    /// [`Window::on_key`] this follows the keyboard layout, dead keys and input methods.
+    /// Control characters such as backspace (`'\u{8}'`) and enter (`'\r'`) are included.
+    pub fn on_char(&mut self, handler: impl FnMut(&mut Window, char) + 'static) {
+        self.char_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback invoked for relative mouse motion from raw input while the window has
+    /// focus, see [`MouseMotion`].
+    pub fn on_mouse_motion(&mut self, handler: impl FnMut(&mut Window, MouseMotion) + 'static) {
+        self.motion_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback invoked for key presses and releases, replacing the default one which
+    /// closes the window when Escape is pressed.
+    pub fn on_key(&mut self, handler: impl FnMut(&mut Window, KeyEvent) + 'static) {
+        self.key_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback invoked when the window is asked to close, e.g. by its close button or
+    /// [`Window::close`]. Returning `false` keeps the window open.
+    pub fn on_close(&mut self, handler: impl FnMut(&mut Window) -> bool + 'static) {
+        self.close_handler = Some(Box::new(handler));
+    }
+
+    /// The alpha up to which pixels let mouse input through, if enabled with
+    /// [`Window::set_alpha_hit_test`].
+    pub fn alpha_hit_test(&self) -> Option<u8> {
+        self.alpha_hit_test
+    }
+
+    /// Lets mouse input through the pixels of the wgpu visual with an alpha up to `threshold`, to
+    /// the windows beneath, while the other pixels capture it as usual, or disables it with
+    /// `None`. Overlays can then be clicked through wherever they are transparent.
+    ///
+    /// After each frame, the render callback renders it again into a texture which is read back
+    /// for its alpha, so the frame costs twice as much GPU time plus a wait for the readback.
+    /// [`Window::on_alpha_hit_test`] avoids that for content whose shape is known on the CPU.
+    /// The mask ignores transforms and layers.
+    ///
+    /// The window stays click-through while the cursor is over transparent pixels, checking the
+    /// cursor position every 50 ms to capture input again.
+    pub fn set_alpha_hit_test(&mut self, threshold: Option<u8>) {
+        self.alpha_hit_test = threshold;
+        if threshold.is_some() {
+            // The mask is read back after rendering.
+            self.request_redraw();
+        } else {
+            self.alpha_mask = None;
+            self.stop_passing_through();
+        }
+    }
+
+    /// The mask read back after the last frame for [`Window::set_alpha_hit_test`].
+    pub fn alpha_mask(&self) -> Option<&AlphaMask> {
+        self.alpha_mask.as_ref()
+    }
+
+    /// Sets the callback deciding whether a point in client coordinates captures mouse input,
+    /// letting it through to the windows beneath otherwise, like [`Window::set_alpha_hit_test`]
+    /// but without reading frames back, e.g. with an [`AlphaMask`] kept on the CPU.
+    pub fn on_alpha_hit_test(
+        &mut self,
+        handler: impl FnMut(&Window, (i32, i32)) -> bool + 'static,
+    ) {
+        self.alpha_hit_test_handler = Some(Box::new(handler));
+    }
+
+    /// Sets the callback deciding what the point under the mouse, in client coordinates, acts as,
+    /// e.g. [`HitTest::Caption`] for custom title bars. Returning `None` falls back to the drag
+    /// region and then to the client area. Points on the frame of decorated windows are left to
+    /// the system.
+    pub fn on_hit_test(
+        &mut self,
+        handler: impl FnMut(&Window, (i32, i32)) -> Option<HitTest> + 'static,
+    ) {
+        self.hit_test_handler = Some(Box::new(handler));
+    }
+
+    pub fn drag_region_height(&self) -> u32 {
+        self.drag_region_height
+    }
+
+    /// Makes a strip `height` logical pixels tall along the top of the client area move the
+    /// window when dragged, like a title bar. Zero, the default, disables it.
+    pub fn set_drag_region_height(&mut self, height: u32) {
+        self.drag_region_height = height;
+    }
+
+    pub fn resize_border(&self) -> u32 {
+        self.resize_border
+    }
+
+    /// Sets how far into the client area of a resizable borderless window its edges resize it,
+    /// in logical pixels, defaults to 8. Decorated windows have the system frame instead.
+    pub fn set_resize_border(&mut self, thickness: u32) {
+        self.resize_border = thickness;
+    }
+
+    /// Sets the callback encoding and submitting each frame on `WM_PAINT`. The surface texture is
+    /// acquired before and presented after it runs. Defaults to [`SurfaceState::draw`].
+    pub fn on_render(&mut self, handler: impl FnMut(&SurfaceState, &wgpu::TextureView) + 'static) {
+        self.render_handler = Box::new(handler);
+    }
+
+    /// Marks the window as needing a new frame and invalidates the client area, so it is rendered
+    /// by the next `WM_PAINT`.
+    ///
+    /// Other paints, e.g. when the window is uncovered, are validated without rendering: DComp
+    /// keeps showing the last presented frame, so mostly static content only costs GPU time when
+    /// it changes.
+    pub fn request_redraw(&self) {
+        self.needs_redraw.set(true);
+        unsafe {
+            let _ = InvalidateRect(self.hwnd, None, false);
+        }
+    }
+
+    /// Commits the visual property changes made since the last commit, e.g. by
+    /// [`Window::set_transform`] or [`Window::set_rounded_clip`], without waiting for the next
+    /// paint.
+    ///
+    /// Otherwise changes are batched into a single commit per frame, made by the paint handler
+    /// after rendering, so changes made together show together.
+    pub fn commit(&self) -> Result<()> {
+        if !self.needs_commit.replace(false) {
+            return Ok(());
+        }
+        let Some(desktop) = &self.desktop else {
+            return Ok(());
+        };
+        trace!(target: LOG_TARGET, "commit");
+        unsafe { desktop.Commit() }.map_err(Error::DComp)
+    }
+
+    /// Marks visual properties as changed and invalidates the client area, so they are committed
+    /// by the next `WM_PAINT` without rendering a frame, or by [`Window::commit`].
+    ///
+    /// Call this after changing visuals created from [`Window::desktop_device`], so the changes
+    /// are committed along with the window's own.
+    pub fn schedule_commit(&self) {
+        self.needs_commit.set(true);
+        unsafe {
+            let _ = InvalidateRect(self.hwnd, None, false);
+        }
+    }
+
+    pub fn loop_mode(&self) -> LoopMode {
+        self.loop_mode
+    }
+
+    /// Changes how the message loop waits for messages, taking effect on its next iteration.
+    pub fn set_loop_mode(&mut self, loop_mode: LoopMode) {
+        self.loop_mode = loop_mode;
+    }
+
+    pub fn frame_rate_limit(&self) -> Option<u32> {
+        self.frame_rate_limit
+    }
+
+    /// Caps the frames per second rendered by [`LoopMode::Poll`], [`LoopMode::CompositorClock`]
+    /// and [`LoopMode::LowLatency`] loops, e.g. at 30 to save power, or removes the cap with
+    /// `None`. Frames are still rendered when the window is invalidated.
+    ///
+    /// While every continuously rendered window is capped or paced, see
+    /// [`Window::set_present_pacing`], the loop sleeps on a high-resolution waitable timer until
+    /// the next frame is due, waking up early for messages.
+    pub fn set_frame_rate_limit(&mut self, limit: Option<u32>) {
+        self.frame_rate_limit = limit.filter(|&limit| limit > 0);
+        self.next_frame = None;
+    }
+
+    pub fn present_pacing(&self) -> bool {
+        self.present_pacing
+    }
+
+    /// Paces continuously rendered frames to the composition rate while the window's surface
+    /// presents without waiting for vblanks, with [`wgpu::PresentMode::Mailbox`] or
+    /// [`wgpu::PresentMode::Immediate`], and has no [`Window::set_frame_rate_limit`]. Defaults to
+    /// `true`.
+    ///
+    /// With [`wgpu::PresentMode::Fifo`], presenting blocks until a vblank frees a buffer, which
+    /// keeps a polling loop at the refresh rate. The other modes never block, so switching to
+    /// them, e.g. with [`Window::set_vsync`], would otherwise turn the loop into a busy loop
+    /// rendering thousands of frames nobody sees. Paced frames are scheduled like under a frame
+    /// rate limit at the composition rate, with the loop sleeping in between, so they still
+    /// present without waiting for vblanks. Disable pacing to measure the uncapped frame rate.
+    pub fn set_present_pacing(&mut self, pacing: bool) {
+        self.present_pacing = pacing;
+        self.next_frame = None;
+    }
+
+    /// The time between continuous frames: the frame rate limit, or the composition interval
+    /// if presents are paced, or `None` to render as fast as presents allow.
+    fn frame_interval(&self) -> Option<Duration> {
+        if let Some(limit) = self.frame_rate_limit {
+            return Some(Duration::from_secs_f64(1.0 / limit as f64));
+        }
+        let present_mode = self.wgpu_state.as_ref()?.surface_config.present_mode;
+        if !self.present_pacing
+            || !matches!(
+                present_mode,
+                wgpu::PresentMode::Mailbox | wgpu::PresentMode::Immediate
+            )
+        {
+            return None;
+        }
+        let mut statistics = Default::default();
+        unsafe { self.desktop.as_ref()?.GetFrameStatistics(&mut statistics) }.ok()?;
+        Some(CompositionStatistics::from(statistics).refresh_interval())
+            .filter(|interval| !interval.is_zero())
+    }
+
+    /// Whether the next continuous frame is due under the frame rate limit or present pacing at
+    /// `now`, and if so schedules the one after it.
+    fn frame_due(&mut self, now: Instant) -> bool {
+        let Some(interval) = self.frame_interval() else {
+            self.next_frame = None;
+            return true;
+        };
+        if self.next_frame.is_some_and(|next| now < next) {
+            return false;
+        }
+        self.next_frame = Some(match self.next_frame {
+            // Keeps a steady cadence, unless frames fell more than an interval behind.
+            Some(next) if now - next < interval => next + interval,
+            _ => now + interval,
+        });
+        true
+    }
+
+    /// Whether the window is the active window, as last reported by `WM_ACTIVATE`.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Whether the message loop stops rendering the window continuously while it is inactive.
+    pub fn pause_when_inactive(&self) -> bool {
+        self.pause_when_inactive
+    }
+
+    /// Stops rendering the window after every iteration of a [`LoopMode::Poll`],
+    /// [`LoopMode::CompositorClock`] or [`LoopMode::LowLatency`] loop while it isn't the active
+    /// window, so backgrounded windows don't keep the GPU busy. It is still painted when
+    /// invalidated, e.g. by [`Window::request_redraw`]. Defaults to `true`.
+    ///
+    /// Minimized windows are never rendered continuously.
+    pub fn set_pause_when_inactive(&mut self, pause: bool) {
+        self.pause_when_inactive = pause;
+    }
+
+    /// Whether the message loop should render the window after every iteration.
+    fn renders_continuously(&self) -> bool {
+        if self.loop_mode == LoopMode::Wait {
+            return false;
+        }
+        unsafe {
+            IsWindow(self.hwnd).as_bool()
+                && !IsIconic(self.hwnd).as_bool()
+                && (self.focused || !self.pause_when_inactive)
+        }
+    }
+
+    pub fn min_size(&self) -> Option<(u32, u32)> {
+        self.min_size
+    }
+
+    /// Sets the smallest client area size the user can resize the window to, in logical pixels
+    /// scaled by [`Window::scale_factor`], or removes the constraint. Applies from the next resize.
+    pub fn set_min_size(&mut self, size: Option<(u32, u32)>) {
+        self.min_size = size;
+    }
+
+    pub fn max_size(&self) -> Option<(u32, u32)> {
+        self.max_size
+    }
+
+    /// Like [`Window::set_min_size`], but for the largest size, which also bounds the maximized
+    /// window. Fullscreen windows are not constrained.
+    pub fn set_max_size(&mut self, size: Option<(u32, u32)>) {
+        self.max_size = size;
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.windowed.is_some()
+    }
+
+    /// Switches to borderless fullscreen covering the monitor the window is on, or restores the
+    /// style and geometry the window had before. The surface is resized by the resulting
+    /// `WM_SIZE`.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) -> Result<()> {
+        if fullscreen == self.is_fullscreen() {
+            return Ok(());
+        }
+
+        unsafe {
+            // Only the style changes, the extended style keeps `WS_EX_NOREDIRECTIONBITMAP`.
+            let (style, rect) = match self.windowed.take() {
+                Some(windowed) => windowed,
+                None => {
+                    let style = WINDOW_STYLE(GetWindowLongPtrW(self.hwnd, GWL_STYLE) as u32);
+                    let mut rect = RECT::default();
+                    GetWindowRect(self.hwnd, &mut rect)?;
+                    self.windowed = Some((style, rect));
+
+                    let monitor = MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST);
+                    let mut info = MONITORINFO {
+                        cbSize: size_of::<MONITORINFO>() as u32,
+                        ..Default::default()
+                    };
+                    GetMonitorInfoA(monitor, &mut info).ok()?;
+                    let fullscreen_style = style
+                        & !(WS_CAPTION | WS_SIZEBOX | WS_SYSMENU | WS_MAXIMIZEBOX | WS_MINIMIZEBOX)
+                        | WS_POPUP;
+                    (fullscreen_style, info.rcMonitor)
+                }
+            };
+
+            SetWindowLongPtrW(self.hwnd, GWL_STYLE, style.0 as _);
+            SetWindowPos(
+                self.hwnd,
+                None,
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                SWP_NOZORDER | SWP_NOOWNERZORDER | SWP_FRAMECHANGED,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_topmost(&self) -> bool {
+        self.topmost
+    }
+
+    /// Keeps the window above all non-topmost windows, or returns it to the normal Z order. Only
+    /// the Z order changes, so the DComp target and swapchain are left alone. Before the window
+    /// is created, this applies once it is.
+    pub fn set_topmost(&mut self, topmost: bool) -> Result<()> {
+        self.topmost = topmost;
+        unsafe {
+            if !IsWindow(self.hwnd).as_bool() {
+                return Ok(());
+            }
+            SetWindowPos(
+                self.hwnd,
+                if topmost {
+                    HWND_TOPMOST
+                } else {
+                    HWND_NOTOPMOST
+                },
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE | SWP_NOOWNERZORDER,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn is_click_through(&self) -> bool {
+        self.click_through
+    }
+
+    /// Lets mouse input pass through the whole window to whatever is below it, by toggling
+    /// `WS_EX_TRANSPARENT` together with `WS_EX_LAYERED`, without which clicks would only pass to
+    /// sibling windows. Keyboard input is unaffected. Before the window is created, this applies
+    /// once it is.
+    ///
+    /// Layering normally redirects the window into a bitmap composed with its layered
+    /// attributes, but with `WS_EX_NOREDIRECTIONBITMAP` there is no bitmap: the content still comes
+    /// only from DComp, and the opaque layered attributes set here only keep the window visible.
+    pub fn set_click_through(&mut self, click_through: bool) -> Result<()> {
+        // Clicking through everywhere overrides passing through transparent pixels.
+        self.stop_passing_through();
+        self.click_through = click_through;
+        unsafe {
+            if !IsWindow(self.hwnd).as_bool() {
+                return Ok(());
+            }
+            apply_click_through(self.hwnd, click_through)?;
+        }
+        Ok(())
+    }
+
+    /// Destroys the icon once the window no longer uses it.
+    fn destroy_icon(&mut self) {
+        if let Some(icon) = self.icon.take() {
+            unsafe {
+                let _ = DestroyIcon(icon);
+            }
+        }
+    }
+
+    /// Whether the surface is blended over the content beneath the window rather than opaque, as
+    /// requested by the surface descriptor or [`Window::set_transparent`].
+    pub fn is_transparent(&self) -> bool {
+        self.surface_descriptor.alpha_mode != wgpu::CompositeAlphaMode::Opaque
+    }
+
+    /// Switches the window and layer surfaces between
+    /// [`wgpu::CompositeAlphaMode::PreMultiplied`], blended over whatever is beneath the window,
+    /// and [`wgpu::CompositeAlphaMode::Opaque`], ignoring alpha.
+    ///
+    /// DXGI can't change the alpha mode of an existing swapchain, so the device resources are
+    /// released and recreated by the next paint, as after device loss.
+    pub fn set_transparent(&mut self, transparent: bool) {
+        if transparent == self.is_transparent() {
+            return;
+        }
+
+        self.surface_descriptor.alpha_mode = if transparent {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            wgpu::CompositeAlphaMode::Opaque
+        };
+        debug!(
+            target: LOG_TARGET,
+            "alpha mode {:?}", self.surface_descriptor.alpha_mode
+        );
+        self.release_device_resources();
+        self.request_redraw();
+    }
+
+    /// Whether the surfaces present with vsync, as requested by the surface descriptor or
+    /// [`Window::set_vsync`].
+    pub fn is_vsync(&self) -> bool {
+        !matches!(
+            self.surface_descriptor.present_mode,
+            wgpu::PresentMode::Immediate
+                | wgpu::PresentMode::Mailbox
+                | wgpu::PresentMode::AutoNoVsync
+        )
+    }
+
+    /// Switches the window and layer surfaces between [`wgpu::PresentMode::Fifo`], waiting for
+    /// vblanks, and [`wgpu::PresentMode::Immediate`], falling back to `Mailbox` where
+    /// unsupported.
+    ///
+    /// Unlike [`Window::set_transparent`], this only reconfigures the existing surfaces, the
+    /// devices and everything created from them are kept. The mode is kept for surfaces
+    /// recreated after device loss.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        let present_mode = if vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        };
+        self.surface_descriptor.present_mode = present_mode;
+
+        let layers = self
+            .layers
+            .iter_mut()
+            .filter_map(|layer| match &mut layer.content {
+                Some(LayerContent::Swapchain(state)) => Some(state),
+                _ => None,
+            });
+        for state in self.wgpu_state.iter_mut().chain(layers) {
+            state.set_present_mode(present_mode);
+        }
+        self.request_redraw();
+    }
+
+    /// Asks the window to close, as if the user had clicked its close button.
+    pub fn close(&self) {
+        unsafe {
+            let _ = PostMessageA(self.hwnd, WM_CLOSE, WPARAM(0), LPARAM(0));
+        }
+    }
+
+    /// Calls the handler stored in `slot` with `event`. The handler is taken out of the window
+    /// while it runs, so it can freely use the window, including replacing itself.
+    fn dispatch<E>(&mut self, slot: fn(&mut Self) -> &mut Option<Handler<E>>, event: E) {
+        let Some(mut handler) = slot(self).take() else {
+            return;
+        };
+        handler(self, event);
+        slot(self).get_or_insert(handler);
+    }
+
+    /// Sets [`SurfaceState::draw_triangle`], including on surfaces recreated after device loss.
+    pub fn set_draw_triangle(&mut self, draw_triangle: bool) {
+        self.draw_triangle = draw_triangle;
+        if let Some(state) = &mut self.wgpu_state {
+            state.draw_triangle = draw_triangle;
+        }
+        self.request_redraw();
+    }
+
+    /// Calls [`SurfaceState::set_clear_color`], including on surfaces recreated after device
+    /// loss.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+        if let Some(state) = &mut self.wgpu_state {
+            state.set_clear_color(color);
+        }
+        self.request_redraw();
+    }
+
+    /// Calls [`SurfaceState::show_stats`], including on surfaces recreated after device loss.
+    pub fn set_show_stats(&mut self, show: bool) {
+        self.show_stats = show;
+        if let Some(state) = &mut self.wgpu_state {
+            state.show_stats(show);
+        }
+        self.request_redraw();
+    }
+
+    /// Calls [`SurfaceState::set_offscreen`], including on surfaces recreated after device loss.
+    /// The render callback then draws the scene to the intermediate texture.
+    pub fn set_offscreen(&mut self, offscreen: bool) {
+        self.offscreen = offscreen;
+        if let Some(state) = &mut self.wgpu_state {
+            state.set_offscreen(offscreen);
+        }
+        self.request_redraw();
+    }
+
+    /// Calls [`SurfaceState::set_compute`], including on surfaces recreated after device loss.
+    /// Nothing changes if the device doesn't support it.
+    pub fn set_compute(&mut self, compute: bool) -> Result<()> {
+        if let Some(state) = &mut self.wgpu_state {
+            state.set_compute(compute)?;
+        }
+        self.compute = compute;
+        self.request_redraw();
+        Ok(())
+    }
+
+    /// Adds a layer of `width` x `height` at `offset_x`, `offset_y` in the client area, stacked
+    /// above the wgpu visual and any previously added layer. Each layer has its own
+    /// [`SurfaceState`], with its own wgpu device, and DComp blends the layers together.
+    ///
+    /// Layers are created along with the window's device resources, immediately if they already
+    /// exist, and are recreated with them after device loss.
+    pub fn add_layer(
+        &mut self,
+        offset_x: f32,
+        offset_y: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<LayerId> {
+        self.push_layer(Layer::new(
+            (offset_x, offset_y),
+            (width, height),
+            LayerKind::Swapchain,
+        ))
+    }
+
+    /// Like [`Window::add_layer`], but the layer shows a [`CompositionSurface`] instead of a
+    /// swapchain, exercising DComp's non-swapchain path. It is rendered with the window's wgpu
+    /// device, which must use the DX12 backend, see [`WindowBuilder::backends`].
+    ///
+    /// Its render callback is given the window's [`SurfaceState`] with a view of the layer's
+    /// texture. The state's depth buffer and [`crate::Uniforms`] have the window's size, so
+    /// [`SurfaceState::draw`] only works for the layer without
+    /// [`SurfaceStateDescriptor::depth`].
+    pub fn add_composition_surface_layer(
+        &mut self,
+        offset_x: f32,
+        offset_y: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<LayerId> {
+        self.push_layer(Layer::new(
+            (offset_x, offset_y),
+            (width, height),
+            LayerKind::CompositionSurface,
+        ))
+    }
+
+    /// Like [`Window::add_composition_surface_layer`], but the layer shows a virtual surface,
+    /// see [`CompositionSurface::new_virtual`], which can be much larger than the window while
+    /// only the parts drawn take memory.
+    ///
+    /// The layer isn't rendered when the window paints, as that would update all of it. It starts
+    /// out empty and only changes with [`Window::present_dirty`], and
+    /// [`Window::resize_virtual_surface`] and [`Window::trim_virtual_surface`] manage its size
+    /// and memory.
+    pub fn add_virtual_surface_layer(
+        &mut self,
+        offset_x: f32,
+        offset_y: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<LayerId> {
+        self.push_layer(Layer::new(
+            (offset_x, offset_y),
+            (width, height),
+            LayerKind::VirtualSurface,
+        ))
+    }
+
+    /// Like [`Window::add_layer`], but the layer shows a [`crate::D2DSurface`] drawn with Direct2D
+    /// instead of wgpu, through the D2D device the window's DComp device was created from. DComp
+    /// composites it with the wgpu visual and the other layers like any visual.
+    ///
+    /// The layer is drawn whenever the window paints, by the callback set with
+    /// [`Window::on_draw_layer`].
+    pub fn add_d2d_layer(
+        &mut self,
+        offset_x: f32,
+        offset_y: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<LayerId> {
+        self.push_layer(Layer::new(
+            (offset_x, offset_y),
+            (width, height),
+            LayerKind::D2D,
+        ))
+    }
+
+    /// Sets the callback drawing a layer added with [`Window::add_d2d_layer`], see
+    /// [`crate::D2DSurface::draw`]. Defaults to drawing nothing, leaving the layer transparent.
+    pub fn on_draw_layer(
+        &mut self,
+        layer: LayerId,
+        handler: impl FnMut(&ID2D1DeviceContext) + 'static,
+    ) {
+        self.layers[layer.0].draw_handler = Box::new(handler);
+    }
+
+    /// Resizes the virtual surface of a layer added with [`Window::add_virtual_surface_layer`],
+    /// see [`CompositionSurface::resize`]. The size is kept for the surface recreated after
+    /// device loss, which starts out empty again.
+    ///
+    /// Fails with `DXGI_ERROR_UNSUPPORTED` for other layers.
+    pub fn resize_virtual_surface(
+        &mut self,
+        layer: LayerId,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let layer = &mut self.layers[layer.0];
+        if layer.kind != LayerKind::VirtualSurface {
+            return Err(windows::core::Error::new(
+                DXGI_ERROR_UNSUPPORTED,
+                "only virtual surface layers can be resized",
+            )
+            .into());
+        }
+        layer.size = (width, height);
+        if let (Some(LayerContent::CompositionSurface(surface)), Some(device_3d), Some(state)) =
+            (&mut layer.content, &self.device, &self.wgpu_state)
+        {
+            surface.resize(device_3d, &state.device, width, height)?;
+            self.schedule_commit();
+        }
+        Ok(())
+    }
+
+    /// Discards the pixels of the virtual surface of a layer added with
+    /// [`Window::add_virtual_surface_layer`] outside of `keep`, in pixels of the layer, see
+    /// [`CompositionSurface::trim`]. The change shows with the next commit.
+    ///
+    /// Fails with `DXGI_ERROR_UNSUPPORTED` for other layers.
+    pub fn trim_virtual_surface(&mut self, layer: LayerId, keep: &[RECT]) -> Result<()> {
+        match &self.layers[layer.0].content {
+            Some(LayerContent::CompositionSurface(surface)) => surface.trim(keep)?,
+            Some(LayerContent::Swapchain(_) | LayerContent::D2D(_)) => {
+                return Err(windows::core::Error::new(
+                    DXGI_ERROR_UNSUPPORTED,
+                    "only virtual surface layers can be trimmed",
+                )
+                .into());
+            }
+            None => return Ok(()),
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    fn push_layer(&mut self, mut layer: Layer) -> Result<LayerId> {
+        if let (Some(desktop), Some(root), Some(device_3d), Some(state)) = (
+            &self.desktop,
+            &self.root_visual,
+            &self.device,
+            &self.wgpu_state,
+        ) {
+            unsafe {
+                let (visual, content) = layer.create_resources(
+                    desktop,
+                    root,
+                    device_3d,
+                    &state.device,
+                    &self.wgpu_instance,
+                    self.shared_device.as_ref(),
+                    &self.surface_descriptor,
+                )?;
+                desktop.Commit().map_err(Error::DComp)?;
+                layer.content = Some(content);
+                layer.visual = Some(visual);
+            }
+            self.request_redraw();
+        }
+
+        let id = LayerId(self.layers.len());
+        self.layers.push(layer);
+        self.layer_order.push(id);
+        Ok(id)
+    }
+
+    /// Sets the callback rendering `layer`, like [`Window::on_render`] does for the wgpu visual.
+    /// Defaults to [`SurfaceState::draw`].
+    pub fn on_render_layer(
+        &mut self,
+        layer: LayerId,
+        handler: impl FnMut(&SurfaceState, &wgpu::TextureView) + 'static,
+    ) {
+        self.layers[layer.0].render_handler = Box::new(handler);
+    }
+
+    /// Sets the clear color of `layer`'s surface, like [`Window::set_clear_color`]. Layers added
+    /// with [`Window::add_composition_surface_layer`] render with the window's surface, whose
+    /// clear color applies instead.
+    pub fn set_layer_clear_color(&mut self, layer: LayerId, color: wgpu::Color) {
+        let layer = &mut self.layers[layer.0];
+        layer.clear_color = color;
+        if let Some(LayerContent::Swapchain(state)) = &mut layer.content {
+            state.set_clear_color(color);
+        }
+        self.request_redraw();
+    }
+
+    /// The surface of `layer`, while the window has device resources. `None` for layers without
+    /// a swapchain, like those added with [`Window::add_composition_surface_layer`].
+    pub fn layer_surface(&self, layer: LayerId) -> Option<&SurfaceState> {
+        match &self.layers[layer.0].content {
+            Some(LayerContent::Swapchain(state)) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// The composition surface of a layer added with [`Window::add_composition_surface_layer`],
+    /// while the window has device resources.
+    pub fn layer_composition_surface(&self, layer: LayerId) -> Option<&CompositionSurface> {
+        match &self.layers[layer.0].content {
+            Some(LayerContent::CompositionSurface(surface)) => Some(surface),
+            _ => None,
+        }
+    }
+
+    /// Renders `layer` with its render callback and updates only the `dirty` rectangles of its
+    /// composition surface, in pixels of the layer, see [`CompositionSurface::render_dirty_with`].
+    /// The update shows with the next commit, made by the next paint or [`Window::commit`]. Does
+    /// nothing before the window has device resources.
+    ///
+    /// Only layers added with [`Window::add_composition_surface_layer`] and
+    /// [`Window::add_virtual_surface_layer`] support this. Swapchains could present dirty
+    /// rectangles with `IDXGISwapChain1::Present1`, but wgpu always presents whole frames and
+    /// doesn't expose the swapchain it creates, so swapchain layers and the wgpu visual fail with
+    /// `DXGI_ERROR_UNSUPPORTED`, as do layers added with [`Window::add_d2d_layer`].
+    pub fn present_dirty(&mut self, layer: LayerId, dirty: &[RECT]) -> Result<()> {
+        let layer = &mut self.layers[layer.0];
+        let Some(window_state) = &self.wgpu_state else {
+            return Ok(());
+        };
+        match &layer.content {
+            Some(LayerContent::CompositionSurface(surface)) => {
+                let render = &mut layer.render_handler;
+                surface.render_dirty_with(&window_state.device, Some(dirty), |view| {
+                    render(window_state, view)
+                })?;
+            }
+            Some(LayerContent::Swapchain(_) | LayerContent::D2D(_)) => {
+                return Err(windows::core::Error::new(
+                    DXGI_ERROR_UNSUPPORTED,
+                    "only composition surface layers can present dirty rectangles",
+                )
+                .into());
+            }
+            None => return Ok(()),
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// The stacking order of the layers, from the one right above the wgpu visual to the
+    /// topmost. Layers are stacked in the order they were added until [`Window::set_layer_order`].
+    pub fn layer_order(&self) -> &[LayerId] {
+        &self.layer_order
+    }
+
+    /// Restacks the layers in `order`, from the one right above the wgpu visual to the topmost,
+    /// and commits. `order` must contain every layer of the window exactly once. The order is
+    /// kept for visuals recreated after device loss.
+    pub fn set_layer_order(&mut self, order: &[LayerId]) -> Result<()> {
+        let mut seen = vec![false; self.layers.len()];
+        for layer in order {
+            if seen.get(layer.0) != Some(&false) {
+                return Err(windows::core::Error::from(E_INVALIDARG).into());
+            }
+            seen[layer.0] = true;
+        }
+        if order.len() != self.layers.len() {
+            return Err(windows::core::Error::from(E_INVALIDARG).into());
+        }
+        self.layer_order = order.to_vec();
+
+        let (Some(root), Some(wgpu_visual)) = (&self.root_visual, &self.wgpu_visual) else {
+            return Ok(());
+        };
+        let visuals = self
+            .layer_order
+            .iter()
+            .map(|layer| &self.layers[layer.0])
+            .filter(|layer| layer.visible)
+            .filter_map(|layer| layer.visual.as_ref());
+        unsafe { restack_layers(root, wgpu_visual, visuals) }.map_err(Error::DComp)?;
+        self.needs_commit.set(true);
+        self.commit()
+    }
+
+    /// The offset of `layer` from the top-left of the client area, in physical pixels.
+    pub fn layer_offset(&self, layer: LayerId) -> (f32, f32) {
+        self.layers[layer.0].offset
+    }
+
+    /// Moves `layer` to `x`, `y` from the top-left of the client area, in physical pixels, with
+    /// the visual's offset. Cheaper than a transform for plain translation, the compositor only
+    /// moves the visual. The change is committed with the next frame or [`Window::commit`], and
+    /// the offset is kept for visuals recreated after device loss.
+    pub fn set_layer_offset(&mut self, layer: LayerId, x: f32, y: f32) -> Result<()> {
+        let layer = &mut self.layers[layer.0];
+        layer.offset = (x, y);
+
+        let Some(visual) = &layer.visual else {
+            return Ok(());
+        };
+        unsafe {
+            visual.SetOffsetX2(x).map_err(Error::DComp)?;
+            visual.SetOffsetY2(y).map_err(Error::DComp)?;
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// Whether `layer` is shown, see [`Window::set_layer_visible`].
+    pub fn is_layer_visible(&self, layer: LayerId) -> bool {
+        self.layers[layer.0].visible
+    }
+
+    /// Shows or hides `layer` by adding its visual back to the tree or removing it, and commits.
+    ///
+    /// Unlike recreating the layer, hiding keeps its surface and visual, with their offset and
+    /// transform, so showing it again is cheap. Hidden layers aren't rendered and keep their
+    /// place in [`Window::layer_order`]. Visibility is kept for visuals recreated after device
+    /// loss.
+    pub fn set_layer_visible(&mut self, layer: LayerId, visible: bool) -> Result<()> {
+        if std::mem::replace(&mut self.layers[layer.0].visible, visible) == visible {
+            return Ok(());
+        }
+
+        let (Some(root), Some(wgpu_visual), Some(visual)) = (
+            &self.root_visual,
+            &self.wgpu_visual,
+            &self.layers[layer.0].visual,
+        ) else {
+            return Ok(());
+        };
+        unsafe {
+            if visible {
+                // Right above the closest visible layer stacked below it.
+                let below = self
+                    .layer_order
+                    .iter()
+                    .take_while(|&&id| id != layer)
+                    .map(|id| &self.layers[id.0])
+                    .filter(|layer| layer.visible)
+                    .filter_map(|layer| layer.visual.as_ref())
+                    .last()
+                    .unwrap_or(wgpu_visual);
+                root.AddVisual(visual, true, below).map_err(Error::DComp)?;
+            } else {
+                root.RemoveVisual(visual).map_err(Error::DComp)?;
+            }
+        }
+        self.needs_commit.set(true);
+        self.commit()?;
+        if visible {
+            // The layer wasn't rendered while hidden.
+            self.request_redraw();
+        }
+        Ok(())
+    }
+
+    /// Fades the wgpu visual in from transparent to opaque over `duration`. The animation runs on
+    /// the compositor, independently of wgpu rendering. Does nothing before the first paint has
+    /// created the visual tree.
+    pub fn fade_in(&self, duration: Duration) -> Result<()> {
+        let (Some(desktop), Some(visual)) = (&self.desktop, &self.wgpu_visual) else {
+            return Ok(());
+        };
+
+        unsafe {
+            // Opacity is only animatable through `IDCompositionVisual3`, available since Windows 8.1.
+            let visual: IDCompositionVisual3 = visual.cast().map_err(Error::DComp)?;
+
+            let seconds = duration.as_secs_f64();
+            if seconds == 0.0 {
+                visual.SetOpacity2(1.0).map_err(Error::DComp)?;
+            } else {
+                let animation = desktop.CreateAnimation().map_err(Error::DComp)?;
+                // A single linear segment rising from 0, reaching 1 at the end of the duration.
+                animation
+                    .AddCubic(0.0, 0.0, (1.0 / seconds) as f32, 0.0, 0.0)
+                    .map_err(Error::DComp)?;
+                animation.End(seconds, 1.0).map_err(Error::DComp)?;
+                visual.SetOpacity(&animation).map_err(Error::DComp)?;
+            }
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// The 2D transform applied to the wgpu visual.
+    pub fn transform(&self) -> Matrix3x2 {
+        self.transform
+    }
+
+    /// Sets a 2D transform the compositor applies to the wgpu visual, in physical pixels relative
+    /// to the client area's top-left, e.g. [`Matrix3x2::rotation`] with the angle in degrees.
+    /// The transform is kept for visuals recreated after device loss.
+    pub fn set_transform(&mut self, transform: Matrix3x2) -> Result<()> {
+        self.transform = transform;
+        self.update_wgpu_transform()
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Configures the swapchain at `render_scale` times the client size, clamped to
+    /// `0.01..=1.0`, and lets the compositor scale the wgpu visual back up to fill the client
+    /// area, with linear filtering.
+    ///
+    /// The scale is applied before [`Window::transform`], so transforms stay in client pixels,
+    /// while [`Window::clip`] applies to the unscaled surface. Layers are not scaled.
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<()> {
+        self.render_scale = render_scale;
+        if let Some((width, height)) = self.client_size {
+            self.resize(width, height);
+        }
+        self.update_wgpu_transform()
+    }
+
+    /// The size the surface is configured at for a `width` x `height` client area.
+    fn render_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = self.render_scale.clamp(0.01, 1.0);
+        let scaled = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    /// The scale from the surface size to the client size.
+    fn surface_scale(&self) -> Matrix3x2 {
+        let Some((width, height)) = self.client_size.filter(|&(w, h)| w > 0 && h > 0) else {
+            return Matrix3x2::identity();
+        };
+        let (render_width, render_height) = self.render_size(width, height);
+        Matrix3x2 {
+            // Exact ratios rather than the scale, so rounding the size leaves no gap at the edges.
+            M11: width as f32 / render_width as f32,
+            M22: height as f32 / render_height as f32,
+            ..Matrix3x2::identity()
+        }
+    }
+
+    /// [`Window::transform`] preceded by the scale from the surface size to the client size.
+    fn wgpu_transform(&self) -> Matrix3x2 {
+        self.surface_scale() * self.transform
+    }
+
+    /// Applies [`Window::wgpu_transform`], or [`Window::transform_3d`] preceded by the same
+    /// scale, to the wgpu visual, if it exists, and schedules a commit.
+    fn update_wgpu_transform(&self) -> Result<()> {
+        let (Some(desktop), Some(visual)) = (&self.desktop, &self.wgpu_visual) else {
+            return Ok(());
+        };
+        unsafe { self.apply_wgpu_transform(desktop, visual) }.map_err(Error::DComp)?;
+        self.schedule_commit();
+        Ok(())
+    }
+
+    unsafe fn apply_wgpu_transform(
+        &self,
+        desktop: &IDCompositionDesktopDevice,
+        visual: &IDCompositionVisual2,
+    ) -> windows::core::Result<()> {
+        match &self.transform_3d {
+            Some(transform) => {
+                let scale = matrix_4x4(&self.surface_scale());
+                apply_transform_3d(visual, &(scale * transform))
+            }
+            None => apply_transform(desktop, visual, &self.wgpu_transform()),
+        }
+    }
+
+    /// The 3D transform applied to the wgpu visual instead of [`Window::transform`], if any.
+    pub fn transform_3d(&self) -> Option<Matrix4x4> {
+        self.transform_3d
+    }
+
+    /// Sets a 3D transform the compositor applies to the wgpu visual instead of
+    /// [`Window::transform`], in physical pixels relative to the client area's top-left, or goes
+    /// back to the 2D transform with `None`. E.g. [`Matrix4x4::rotation_y`] followed by
+    /// [`Matrix4x4::perspective_projection`], both around the center of the visual, flips it with
+    /// perspective.
+    ///
+    /// Requires `IDCompositionVisual3`, available since Windows 8.1. The transform is kept for
+    /// visuals recreated after device loss.
+    pub fn set_transform_3d(&mut self, transform: Option<Matrix4x4>) -> Result<()> {
+        self.transform_3d = transform;
+        self.update_wgpu_transform()
+    }
+
+    /// Whether the back of the wgpu visual is shown when a 3D transform turns it away from the
+    /// viewer, see [`Window::set_backface_visible`].
+    pub fn is_backface_visible(&self) -> bool {
+        self.backface_visible
+    }
+
+    /// Shows the back of the wgpu visual, mirrored, when [`Window::set_transform_3d`] turns it
+    /// away from the viewer, or culls it. Defaults to `true`.
+    ///
+    /// Requires `IDCompositionVisual3`, available since Windows 8.1. The setting is kept for
+    /// visuals recreated after device loss.
+    pub fn set_backface_visible(&mut self, visible: bool) -> Result<()> {
+        self.backface_visible = visible;
+        self.update_visual_3d()
+    }
+
+    /// How the wgpu visual and its children are ordered in depth, see [`Window::set_depth_mode`].
+    pub fn depth_mode(&self) -> DCOMPOSITION_DEPTH_MODE {
+        self.depth_mode
+    }
+
+    /// Sets how the wgpu visual and its children are ordered in depth, e.g.
+    /// `DCOMPOSITION_DEPTH_MODE_SORTED` to draw them back to front by their 3D transforms rather
+    /// than in tree order. Defaults to `DCOMPOSITION_DEPTH_MODE_TREE`.
+    ///
+    /// Requires `IDCompositionVisual3`, available since Windows 8.1, and Windows 10 for sorted
+    /// and spatial modes. The mode is kept for visuals recreated after device loss.
+    pub fn set_depth_mode(&mut self, mode: DCOMPOSITION_DEPTH_MODE) -> Result<()> {
+        self.depth_mode = mode;
+        self.update_visual_3d()
+    }
+
+    /// Applies the backface visibility and depth mode to the wgpu visual, if it exists, and
+    /// schedules a commit.
+    fn update_visual_3d(&self) -> Result<()> {
+        let Some(visual) = &self.wgpu_visual else {
+            return Ok(());
+        };
+        unsafe { apply_visual_3d(visual, self.backface_visible, self.depth_mode) }
+            .map_err(Error::DComp)?;
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// Scales the wgpu visual by `scale` and rotates it by `degrees` clockwise, both around
+    /// `center`, then moves it by `offset`. See [`Window::set_transform`].
+    pub fn set_scale_rotation_translation(
+        &mut self,
+        scale: f32,
+        degrees: f32,
+        center: (f32, f32),
+        offset: (f32, f32),
+    ) -> Result<()> {
+        let (x, y) = center;
+        let scale = Matrix3x2 {
+            M11: scale,
+            M22: scale,
+            M31: x - scale * x,
+            M32: y - scale * y,
+            ..Matrix3x2::identity()
+        };
+        let transform =
+            scale * Matrix3x2::rotation(degrees, x, y) * Matrix3x2::translation(offset.0, offset.1);
+        self.set_transform(transform)
+    }
+
+    /// The rectangle the wgpu visual is clipped to, if any.
+    pub fn clip(&self) -> Option<D2D_RECT_F> {
+        self.clip
+    }
+
+    /// Clips the wgpu visual to `clip`, in the visual's coordinates before its transform, or
+    /// removes the clip if `None`. The clip is kept for visuals recreated after device loss.
+    pub fn set_clip(&mut self, clip: Option<D2D_RECT_F>) -> Result<()> {
+        self.set_rounded_clip(clip, 0.0)
+    }
+
+    /// Like [`Window::set_clip`], but rounds the corners of `clip` with `radius`. The radius is
+    /// clamped to half the width and height of the rectangle, where opposite corners would meet.
+    pub fn set_rounded_clip(&mut self, clip: Option<D2D_RECT_F>, radius: f32) -> Result<()> {
+        self.clip = clip;
+        self.clip_radius = radius;
+
+        let (Some(desktop), Some(visual)) = (&self.desktop, &self.wgpu_visual) else {
+            return Ok(());
+        };
+        unsafe {
+            apply_clip(desktop, visual, clip.as_ref(), radius).map_err(Error::DComp)?;
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// The visual beneath the wgpu visual blurred by [`Window::enable_backdrop_blur`], while the
+    /// blur is enabled and the window has device resources.
+    ///
+    /// DComp effects apply to the content of the visual they are set on and its children, they
+    /// can't sample what is behind the window. Content to blur is set on this visual.
+    pub fn backdrop_visual(&self) -> Option<&IDCompositionVisual2> {
+        self.backdrop_visual.as_ref()
+    }
+
+    /// Adds a backdrop visual beneath the wgpu visual, blurred with a standard deviation of
+    /// `std_dev` pixels and showing through the transparent parts of the wgpu output. Calling it
+    /// again changes the standard deviation. The blur is kept for visuals recreated after device
+    /// loss.
+    ///
+    /// Requires `IDCompositionDevice3`, available since Windows 10.
+    pub fn enable_backdrop_blur(&mut self, std_dev: f32) -> Result<()> {
+        self.backdrop_blur = Some(std_dev);
+
+        let (Some(desktop), Some(root), Some(wgpu_visual)) =
+            (&self.desktop, &self.root_visual, &self.wgpu_visual)
+        else {
+            return Ok(());
+        };
+        unsafe {
+            match &self.backdrop_visual {
+                Some(backdrop) => apply_blur(desktop, backdrop, std_dev),
+                None => create_backdrop(desktop, root, wgpu_visual, std_dev)
+                    .map(|backdrop| self.backdrop_visual = Some(backdrop)),
+            }
+            .map_err(Error::DComp)?;
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    /// Removes the backdrop visual added by [`Window::enable_backdrop_blur`].
+    pub fn disable_backdrop_blur(&mut self) -> Result<()> {
+        self.backdrop_blur = None;
+
+        let (Some(root), Some(backdrop)) = (&self.root_visual, self.backdrop_visual.take()) else {
+            return Ok(());
+        };
+        unsafe {
+            root.RemoveVisual(&backdrop).map_err(Error::DComp)?;
+        }
+        self.schedule_commit();
+        Ok(())
+    }
+
+    fn create_device_resources(&mut self) -> Result<()> {
+        unsafe {
+            debug_assert!(self.device.is_none());
+            // Nothing is stored on `self` until every resource has been created, so a failure
+            // leaves the window without a device and the next paint starts over.
+            let device_3d = match &self.shared_device {
+                Some(shared) => shared.device_3d.clone(),
+                None => {
+                    create_device_3d(self.d3d_driver_type, self.d3d_debug).map_err(Error::D3D)?
+                }
+            };
+            let device_2d = create_device_2d(&device_3d).map_err(Error::D3D)?;
+            let desktop: IDCompositionDesktopDevice =
+                DCompositionCreateDevice2(&device_2d).map_err(Error::DComp)?;
+
+            // First release any previous target, otherwise `CreateTargetForHwnd` will find the HWND occupied.
+            self.target = None;
+            let (target, root_visual, wgpu_visual) =
+                create_visual_tree(&desktop, self.hwnd).map_err(Error::DComp)?;
+            if self.transform_3d.is_some() || self.wgpu_transform() != Matrix3x2::identity() {
+                self.apply_wgpu_transform(&desktop, &wgpu_visual)
+                    .map_err(Error::DComp)?;
+            }
+            if !self.backface_visible || self.depth_mode != DCOMPOSITION_DEPTH_MODE_TREE {
+                // Only a visual effect, not worth failing the device over on older systems.
+                if let Err(error) =
+                    apply_visual_3d(&wgpu_visual, self.backface_visible, self.depth_mode)
+                {
+                    warn!(target: LOG_TARGET, "failed to set backface visibility and depth mode: {error}");
+                }
+            }
+            if let Some(clip) = &self.clip {
+                apply_clip(&desktop, &wgpu_visual, Some(clip), self.clip_radius)
+                    .map_err(Error::DComp)?;
+            }
+            let backdrop_visual = self
+                .backdrop_blur
+                .map(|std_dev| create_backdrop(&desktop, &root_visual, &wgpu_visual, std_dev))
+                .transpose()
+                .map_err(Error::DComp)?;
+
+            // A `WM_SIZE` may have arrived before the surface existed, its size is the latest.
+            let (width, height) = match self.client_size {
+                Some(size) => size,
+                None => client_size(self.hwnd)?,
+            };
+            let (width, height) = self.render_size(width, height);
+
+            let (instance, shared) = (&self.wgpu_instance, self.shared_device.as_ref());
+            let descriptor = &self.surface_descriptor;
+            let mut state = new_surface_state(
+                instance,
+                shared,
+                wgpu_visual.as_raw(),
+                width,
+                height,
+                descriptor,
+            )?;
+            if let Some(shader) = &self.shader {
+                // Still watch the file if it fails to load, so fixing the shader picks it up.
+                state.shader_path = Some(shader.clone());
+                state.shadertoy = self.shadertoy;
+                if let Err(error) = pollster::block_on(state.reload_shader()) {
+                    warn!(target: LOG_TARGET, "{error}, falling back to the built-in shader");
+                }
+            }
+            state.draw_triangle = self.draw_triangle;
+            state.set_clear_color(self.clear_color);
+            state.set_mouse(self.mouse);
+            state.show_stats(self.show_stats);
+            state.set_offscreen(self.offscreen);
+            if let Err(error) = state.set_compute(self.compute) {
+                warn!(target: LOG_TARGET, "{error}, compute is disabled");
+            }
+
+            let layers = self
+                .layers
+                .iter()
+                .map(|layer| {
+                    layer.create_resources(
+                        &desktop,
+                        &root_visual,
+                        &device_3d,
+                        &state.device,
+                        instance,
+                        shared,
+                        descriptor,
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Layers are created above each other in the order they were added.
+            if self
+                .layer_order
+                .iter()
+                .enumerate()
+                .any(|(i, layer)| layer.0 != i)
+            {
+                let visuals = self
+                    .layer_order
+                    .iter()
+                    .filter(|layer| self.layers[layer.0].visible)
+                    .map(|layer| &layers[layer.0].0);
+                restack_layers(&root_visual, &wgpu_visual, visuals).map_err(Error::DComp)?;
+            }
+
+            desktop.Commit().map_err(Error::DComp)?;
+
+            self.wgpu_state = Some(state);
+            for (layer, (visual, content)) in self.layers.iter_mut().zip(layers) {
+                layer.content = Some(content);
+                layer.visual = Some(visual);
+            }
+            self.wgpu_visual = Some(wgpu_visual);
+            self.backdrop_visual = backdrop_visual;
+            self.root_visual = Some(root_visual);
+            self.target = Some(target);
+            self.desktop = Some(desktop);
+            self.feature_level = Some(device_3d.GetFeatureLevel());
+            self.device = Some(device_3d);
+            Ok(())
+        }
+    }
+
+    /// Releases the wgpu surface together with the D3D and DComp devices it was created from, so
+    /// they are recreated together by the next paint.
+    ///
+    /// Surfaces are released before the visuals they were created from, and visuals before the
+    /// target and the devices owning them.
+    fn release_device_resources(&mut self) {
+        if self.device.is_some() {
+            debug!(
+                target: LOG_TARGET,
+                "release surfaces, visuals, target, DComp device, D3D device"
+            );
+        }
+
+        for layer in &mut self.layers {
+            layer.content = None;
+            layer.visual = None;
+        }
+        self.wgpu_state = None;
+        self.wgpu_visual = None;
+        self.backdrop_visual = None;
+        self.root_visual = None;
+        self.target = None;
+        self.desktop = None;
+        self.device = None;
+    }
+
+    /// Detaches the visual tree from the target and waits for DComp to stop composing it, so it
+    /// can be released without the compositor still presenting it.
+    fn detach_visual_tree(&self) {
+        let (Some(desktop), Some(target)) = (&self.desktop, &self.target) else {
+            return;
+        };
+
+        debug!(target: LOG_TARGET, "detach visual tree");
+        unsafe {
+            let _ = target.SetRoot(None);
+            let _ = desktop.Commit();
+            let _ = desktop.WaitForCommitCompletion();
+        }
+    }
+
+    /// Releases the composition target and creates a new one for the window, with the existing
+    /// root visual as its root, keeping the devices, visuals and surfaces.
+    ///
+    /// This is much cheaper than the full rebuild after device loss, for when only the target is
+    /// broken, e.g. the window's visual tree was invalidated while the device is still fine. Does
+    /// nothing if the device resources haven't been created.
+    pub fn rebuild_target(&mut self) -> Result<()> {
+        let (Some(desktop), Some(root_visual)) = (&self.desktop, &self.root_visual) else {
+            return Ok(());
+        };
+
+        debug!(target: LOG_TARGET, "rebuild target");
+        self.detach_visual_tree();
+        // Released first, otherwise `CreateTargetForHwnd` finds the HWND occupied.
+        self.target = None;
+        unsafe {
+            let target = create_target(desktop, self.hwnd).map_err(Error::DComp)?;
+            target.SetRoot(root_visual).map_err(Error::DComp)?;
+            desktop.Commit().map_err(Error::DComp)?;
+            self.target = Some(target);
+        }
+        Ok(())
+    }
+
+    /// Queries the compositor's timing around the last frame it composed, to correlate wgpu
+    /// presents with the compositor clock, or `None` before the first paint created the device.
+    pub fn frame_statistics(&self) -> Result<Option<CompositionStatistics>> {
+        let Some(desktop) = &self.desktop else {
+            return Ok(None);
+        };
+
+        let mut statistics = Default::default();
+        unsafe { desktop.GetFrameStatistics(&mut statistics) }.map_err(Error::DComp)?;
+        let statistics = CompositionStatistics::from(statistics);
+        debug!(
+            target: LOG_TARGET,
+            "composition at {:.2} Hz, {:?} since the last frame, next in {:?}",
+            statistics.composition_rate,
+            statistics.since_last_frame(),
+            statistics.until_next_frame()
+        );
+        Ok(Some(statistics))
+    }
+
+    /// Makes the next paint fail as if the D3D device had been removed, to exercise the device
+    /// loss recovery path.
+    pub fn simulate_device_removed(&mut self) {
+        self.simulate_device_removed = true;
+        self.request_redraw();
+    }
+
+    fn paint_handler(&mut self) -> Result<()> {
+        unsafe {
+            if let Some(device) = &self.device {
+                trace!(target: LOG_TARGET, "check device");
+                if std::mem::take(&mut self.simulate_device_removed) {
+                    return Err(Error::D3D(DXGI_ERROR_DEVICE_REMOVED.into()));
+                }
+                device.GetDeviceRemovedReason().map_err(Error::D3D)?;
+            } else {
+                debug!(target: LOG_TARGET, "build device");
+                self.create_device_resources()?;
+                // New surfaces have no content yet.
+                self.needs_redraw.set(true);
+            }
+
+            if !self.needs_redraw.replace(false) {
+                trace!(target: LOG_TARGET, "skip paint, nothing changed");
+                self.commit()?;
+                ValidateRect(self.hwnd, None).ok()?;
+                return Ok(());
+            }
+
+            let render = &mut self.render_handler;
+            let window_state = self.wgpu_state.as_mut().unwrap();
+            let visual = self.wgpu_visual.as_ref().unwrap();
+            render_recreating_lost(window_state, &self.wgpu_instance, visual, &mut *render)?;
+            let window_state = &*window_state;
+            if self.alpha_hit_test.is_some() && self.alpha_hit_test_handler.is_none() {
+                match window_state.read_alpha_mask_with(|state, view| render(state, view)) {
+                    Ok(mask) => self.alpha_mask = Some(mask),
+                    Err(error) => {
+                        warn!(target: LOG_TARGET, "failed to read the alpha mask: {error}")
+                    }
+                }
+            }
+
+            for layer in self.layers.iter_mut().filter(|layer| layer.visible) {
+                let render = &mut layer.render_handler;
+                match &mut layer.content {
+                    Some(LayerContent::Swapchain(state)) => {
+                        let visual = layer.visual.as_ref().unwrap();
+                        render_recreating_lost(state, &self.wgpu_instance, visual, render)?
+                    }
+                    Some(LayerContent::CompositionSurface(surface)) if !surface.is_virtual() => {
+                        surface
+                            .render_with(&window_state.device, |view| render(window_state, view))?;
+                        // Unlike presents, composition surface updates only show once committed.
+                        self.needs_commit.set(true);
+                    }
+                    Some(LayerContent::D2D(surface)) => {
+                        surface.draw(|context| (layer.draw_handler)(context))?;
+                        self.needs_commit.set(true);
+                    }
+                    // Virtual surfaces are only updated by `present_dirty`.
+                    _ => {}
+                }
+            }
+            self.commit()?;
+
+            ValidateRect(self.hwnd, None).ok()?;
+        }
+
+        Ok(())
+    }
+
+    /// Asks the close handler whether the window may close, and if so releases the device
+    /// resources before the window is destroyed.
+    fn close_request_handler(&mut self) -> bool {
+        if let Some(mut handler) = self.close_handler.take() {
+            let close = handler(self);
+            self.close_handler.get_or_insert(handler);
+            if !close {
+                return false;
+            }
+        }
+
+        self.detach_visual_tree();
+        self.release_device_resources();
+        true
+    }
+
+    fn size_handler(&mut self, lparam: LPARAM) {
+        let w = loword(lparam.0 as u32) as u32;
+        let h = hiword(lparam.0 as u32) as u32;
+
+        self.resize(w, h);
+    }
+
+    /// Resizes the surface for a `width` x `height` client area, and updates the transform
+    /// scaling it up if it is rendered at a lower resolution.
+    fn resize(&mut self, width: u32, height: u32) {
+        self.client_size = Some((width, height));
+        let (render_width, render_height) = self.render_size(width, height);
+        if let Some(state) = &mut self.wgpu_state {
+            state.resize(render_width, render_height);
+        }
+        if self.render_scale != 1.0 {
+            if let Err(error) = self.update_wgpu_transform() {
+                error!(target: LOG_TARGET, "failed to update the render scale: {error}");
+            }
+        }
+        // Reconfiguring the surface discards its frames.
+        self.request_redraw();
+    }
+
+    fn hit_test_handler(&mut self, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        let default = unsafe { DefWindowProcW(self.hwnd, WM_NCHITTEST, wparam, lparam) };
+        if default != HitTest::Client.to_lresult() {
+            return default;
+        }
+
+        // Like wheel messages, hit tests carry screen coordinates.
+        let mut point = POINT {
+            x: loword(lparam.0 as u32) as i16 as i32,
+            y: hiword(lparam.0 as u32) as i16 as i32,
+        };
+        unsafe {
+            let _ = ScreenToClient(self.hwnd, &mut point);
+        }
+
+        if let Some(mut handler) = self.hit_test_handler.take() {
+            let hit = handler(self, (point.x, point.y));
+            self.hit_test_handler.get_or_insert(handler);
+            if let Some(hit) = hit {
+                return hit.to_lresult();
+            }
+        }
+
+        if let Some(edge) = self.resize_edge(point) {
+            return edge.to_lresult();
+        }
+
+        let drag_height = (self.drag_region_height as f64 * self.scale_factor).round() as i32;
+        if point.y < drag_height {
+            return HitTest::Caption.to_lresult();
+        }
+
+        if !self.captures_input(point) {
+            // `HTTRANSPARENT` only reaches windows of this thread, other processes need the
+            // window to be click-through.
+            self.start_passing_through();
+            return HitTest::Transparent.to_lresult();
+        }
+        default
+    }
+
+    /// Whether `point`, in client coordinates, captures mouse input according to the
+    /// [`Window::on_alpha_hit_test`] callback or the alpha mask, if any.
+    fn captures_input(&mut self, point: POINT) -> bool {
+        if let Some(mut handler) = self.alpha_hit_test_handler.take() {
+            let captures = handler(self, (point.x, point.y));
+            self.alpha_hit_test_handler.get_or_insert(handler);
+            return captures;
+        }
+
+        let (Some(threshold), Some(mask), Some((width, height))) =
+            (self.alpha_hit_test, &self.alpha_mask, self.client_size)
+        else {
+            return true;
+        };
+        if point.x < 0 || point.y < 0 || width == 0 || height == 0 {
+            return true;
+        }
+        // The mask has the surface size, smaller than the client area with a render scale.
+        let x = point.x as u64 * mask.width() as u64 / width as u64;
+        let y = point.y as u64 * mask.height() as u64 / height as u64;
+        mask.alpha(x as u32, y as u32)
+            .is_none_or(|alpha| alpha > threshold)
+    }
+
+    /// Makes the window click-through while the cursor is over transparent pixels, polling the
+    /// cursor with a timer since the window gets no mouse messages meanwhile.
+    fn start_passing_through(&mut self) {
+        if self.passing_through || self.click_through {
+            return;
+        }
+        trace!(target: LOG_TARGET, "pass mouse input through");
+        unsafe {
+            if let Err(error) = apply_click_through(self.hwnd, true) {
+                warn!(target: LOG_TARGET, "failed to pass mouse input through: {error}");
+                return;
+            }
+            SetTimer(
+                self.hwnd,
+                PASS_THROUGH_TIMER,
+                PASS_THROUGH_POLL_INTERVAL,
+                None,
+            );
+        }
+        self.passing_through = true;
+    }
+
+    fn stop_passing_through(&mut self) {
+        if !std::mem::take(&mut self.passing_through) {
+            return;
+        }
+        trace!(target: LOG_TARGET, "capture mouse input");
+        unsafe {
+            let _ = KillTimer(self.hwnd, PASS_THROUGH_TIMER);
+            if !self.click_through {
+                let _ = apply_click_through(self.hwnd, false);
+            }
+        }
+    }
+
+    /// Captures mouse input again once the cursor left the client area or moved over pixels
+    /// capturing it.
+    fn pass_through_timer_handler(&mut self) {
+        let mut point = POINT::default();
+        let mut rect = RECT::default();
+        unsafe {
+            if GetCursorPos(&mut point).is_err()
+                || !ScreenToClient(self.hwnd, &mut point).as_bool()
+                || GetClientRect(self.hwnd, &mut rect).is_err()
+            {
+                self.stop_passing_through();
+                return;
+            }
+        }
+        let inside = point.x >= rect.left
+            && point.x < rect.right
+            && point.y >= rect.top
+            && point.y < rect.bottom;
+        if !inside || self.captures_input(point) {
+            self.stop_passing_through();
+        }
+    }
+
+    /// The edge or corner of a resizable borderless window `point` resizes, if any. Maximized and
+    /// fullscreen windows can't be resized.
+    fn resize_edge(&self, point: POINT) -> Option<HitTest> {
+        if self.decorations
+            || !self.style.contains(WS_SIZEBOX)
+            || self.is_fullscreen()
+            || unsafe { IsZoomed(self.hwnd).as_bool() }
+        {
+            return None;
+        }
+
+        let (width, height) = client_size(self.hwnd).ok()?;
+        let border = (self.resize_border as f64 * self.scale_factor).round() as i32;
+        let left = point.x < border;
+        let right = point.x >= width as i32 - border;
+        let top = point.y < border;
+        let bottom = point.y >= height as i32 - border;
+        Some(match (left, right, top, bottom) {
+            (true, _, true, _) => HitTest::TopLeft,
+            (_, true, true, _) => HitTest::TopRight,
+            (true, _, _, true) => HitTest::BottomLeft,
+            (_, true, _, true) => HitTest::BottomRight,
+            (true, ..) => HitTest::Left,
+            (_, true, ..) => HitTest::Right,
+            (_, _, true, _) => HitTest::Top,
+            (.., true) => HitTest::Bottom,
+            _ => return None,
+        })
+    }
+
+    /// Makes the client area of borderless windows cover the whole window, hiding the sizing
+    /// frame, or returns `None` to let the system compute it.
+    fn calc_size_handler(&self, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
+        if self.decorations || wparam.0 == 0 {
+            return None;
+        }
+
+        // Maximized windows extend past the monitor by their frame, keep them on the work area.
+        unsafe {
+            if IsZoomed(self.hwnd).as_bool() {
+                let params = &mut *(lparam.0 as *mut NCCALCSIZE_PARAMS);
+                let monitor = MonitorFromWindow(self.hwnd, MONITOR_DEFAULTTONEAREST);
+                let mut info = MONITORINFO {
+                    cbSize: size_of::<MONITORINFO>() as u32,
+                    ..Default::default()
+                };
+                if GetMonitorInfoW(monitor, &mut info).as_bool() {
+                    params.rgrc[0] = info.rcWork;
+                }
+            }
+        }
+        Some(LRESULT(0))
+    }
+
+    /// Converts the client size constraints to window sizes at the current DPI.
+    fn min_max_info_handler(&self, lparam: LPARAM) -> Result<()> {
+        if self.is_fullscreen() {
+            return Ok(());
+        }
+
+        let dpi = (self.scale_factor * USER_DEFAULT_SCREEN_DPI as f64).round() as u32;
+        let window_size = |(width, height): (u32, u32)| -> Result<POINT> {
+            let mut rect = RECT {
+                left: 0,
+                top: 0,
+                right: (width as f64 * self.scale_factor).round() as _,
+                bottom: (height as f64 * self.scale_factor).round() as _,
+            };
+            // Borderless windows are all client area.
+            if self.decorations {
+                unsafe {
+                    AdjustWindowRectExForDpi(
+                        &mut rect,
+                        self.style,
+                        false,
+                        WS_EX_NOREDIRECTIONBITMAP,
+                        dpi,
+                    )?;
+                }
+            }
+            Ok(POINT {
+                x: rect.right - rect.left,
+                y: rect.bottom - rect.top,
+            })
+        };
+
+        let info = unsafe { &mut *(lparam.0 as *mut MINMAXINFO) };
+        if let Some(size) = self.min_size {
+            info.ptMinTrackSize = window_size(size)?;
+        }
+        if let Some(size) = self.max_size {
+            info.ptMaxTrackSize = window_size(size)?;
+            info.ptMaxSize = info.ptMaxTrackSize;
+        }
+        Ok(())
+    }
+
+    fn dpi_change_handler(&mut self, wparam: WPARAM, lparam: LPARAM) -> Result<()> {
+        self.scale_factor = scale_factor(hiword(wparam.0 as u32) as u32);
+
+        unsafe {
+            // Windows suggests a rect that keeps the window at the same logical size.
+            let suggested = &*(lparam.0 as *const RECT);
+            SetWindowPos(
+                self.hwnd,
+                None,
+                suggested.left,
+                suggested.top,
+                suggested.right - suggested.left,
+                suggested.bottom - suggested.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            )?;
+        }
+
+        let (width, height) = client_size(self.hwnd)?;
+        self.resize(width, height);
+
+        Ok(())
+    }
+
+    /// Monitors were added, removed or changed resolution, which can remove the device or leave
+    /// the DComp target stale. A removed device is released so the next paint recreates
+    /// everything, otherwise the window is just redrawn.
+    fn display_change_handler(&mut self, wparam: WPARAM, lparam: LPARAM) {
+        debug!(
+            target: LOG_TARGET,
+            "display changed: {}x{}, {} bpp",
+            loword(lparam.0 as u32),
+            hiword(lparam.0 as u32),
+            wparam.0
+        );
+
+        if let Some(device) = &self.device {
+            if let Err(error) = unsafe { device.GetDeviceRemovedReason() } {
+                warn!(target: LOG_TARGET, "device removed by display change: {error}");
+                self.release_device_resources();
+            }
+        }
+        self.request_redraw();
+    }
+
+    fn shader_changed_handler(&mut self) {
+        let Some(state) = &mut self.wgpu_state else {
+            return;
+        };
+
+        // Keep rendering with the previous pipeline until the shader compiles again.
+        if let Err(error) = pollster::block_on(state.reload_shader()) {
+            error!(target: LOG_TARGET, "{error}");
+            return;
+        }
+
+        self.request_redraw();
+    }
+
+    /// Follows the left button like Shadertoy's `iMouse`, in pixels of the window's surface,
+    /// and passes it on to the surface.
+    fn track_mouse(&mut self, event: MouseEvent) {
+        let scale = self.surface_scale();
+        let Some(state) = &mut self.wgpu_state else {
+            return;
+        };
+        let (x, y) = event.position();
+        let (x, y) = (
+            x as f32 / scale.M11,
+            state.surface_config.height as f32 - y as f32 / scale.M22,
+        );
+        match event {
+            MouseEvent::Pressed {
+                button: MouseButton::Left,
+                ..
+            } => self.mouse = [x, y, x, y],
+            MouseEvent::Moved {
+                buttons: MouseButtons { left: true, .. },
+                ..
+            } => [self.mouse[0], self.mouse[1]] = [x, y],
+            MouseEvent::Released {
+                button: MouseButton::Left,
+                ..
+            } => [self.mouse[2], self.mouse[3]] = [-self.mouse[2].abs(), -self.mouse[3].abs()],
+            _ => return,
+        }
+        state.set_mouse(self.mouse);
+    }
+
+    fn message_handler(&mut self, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if let Some(event) = MouseEvent::from_message(message, wparam, lparam) {
+            self.track_mouse(event);
+            self.dispatch(|window| &mut window.mouse_handler, event);
+            return LRESULT(0);
+        }
+
+        if let Some(event) = ScrollEvent::from_message(self.hwnd, message, wparam, lparam) {
+            self.dispatch(|window| &mut window.scroll_handler, event);
+            return LRESULT(0);
+        }
+
+        if let Some(event) = KeyEvent::from_message(message, wparam, lparam) {
+            self.dispatch(|window| &mut window.key_handler, event);
+            return LRESULT(0);
+        }
+
+        unsafe {
+            match message {
+                WM_PAINT => {
+                    self.paint_handler().unwrap_or_else(|error| {
+                        // Device loss can cause rendering to fail and should not be considered fatal.
+                        warn!(target: LOG_TARGET, "WM_PAINT failed: {error}");
+                        self.release_device_resources();
+
+                        // Anything but device loss is likely to fail again, so wait for the next
+                        // invalidation rather than retrying immediately.
+                        if !matches!(error, Error::D3D(_)) {
+                            let _ = ValidateRect(self.hwnd, None);
+                        }
+                    });
+                }
+                // With `WS_EX_NOREDIRECTIONBITMAP` there is no redirection surface for GDI to
+                // erase, an erase would only flash the background before DComp presents.
+                WM_ERASEBKGND => return LRESULT(1),
+                WM_SIZE => self.size_handler(lparam),
+                WM_NCHITTEST => return self.hit_test_handler(wparam, lparam),
+                WM_NCCALCSIZE => {
+                    if let Some(result) = self.calc_size_handler(wparam, lparam) {
+                        return result;
+                    }
+                    return DefWindowProcW(self.hwnd, message, wparam, lparam);
+                }
+                WM_INPUT => {
+                    if let Some(motion) = MouseMotion::from_raw_input(lparam) {
+                        self.dispatch(|window| &mut window.motion_handler, motion);
+                    }
+                    // Lets the system clean up after the input.
+                    return DefWindowProcW(self.hwnd, message, wparam, lparam);
+                }
+                WM_CHAR => {
+                    if let Some(char) = self.utf16.push(wparam.0 as u16) {
+                        self.dispatch(|window| &mut window.char_handler, char);
+                    }
+                }
+                WM_GETMINMAXINFO => {
+                    if let Err(error) = self.min_max_info_handler(lparam) {
+                        error!(target: LOG_TARGET, "WM_GETMINMAXINFO failed: {error}");
+                    }
+                }
+                WM_ACTIVATE => {
+                    self.focused = loword(wparam.0 as u32) as u32 != WA_INACTIVE;
+                    trace!(target: LOG_TARGET, "focused: {}", self.focused);
+                    // Still let the default handling set the keyboard focus.
+                    return DefWindowProcW(self.hwnd, message, wparam, lparam);
+                }
+                WM_DPICHANGED => {
+                    if let Err(error) = self.dpi_change_handler(wparam, lparam) {
+                        error!(target: LOG_TARGET, "WM_DPICHANGED failed: {error}");
+                    }
+                }
+                WM_DISPLAYCHANGE => self.display_change_handler(wparam, lparam),
+                WM_TIMER if wparam.0 == PASS_THROUGH_TIMER => self.pass_through_timer_handler(),
+                WM_SHADER_CHANGED => self.shader_changed_handler(),
+                WM_DESTROY => {
+                    self.destroy_icon();
+                    return DefWindowProcW(self.hwnd, message, wparam, lparam);
+                }
+                WM_CLOSE => {
+                    if self.close_request_handler() {
+                        let _ = DestroyWindow(self.hwnd);
+                    }
+                }
+                _ => return DefWindowProcW(self.hwnd, message, wparam, lparam),
+            }
+        }
+
+        LRESULT(0)
+    }
+
+    /// Creates the Win32 window and pumps messages until it is destroyed.
+    pub fn run(&mut self) -> Result<()> {
+        run_event_loop(&mut [self])
+    }
+
+    /// Like [`Window::run`], but renders continuously, waiting on the compositor clock with
+    /// `DCompositionWaitForCompositorClock` before each frame. Messages are pumped without
+    /// blocking in between.
+    ///
+    /// Presenting waits as well depending on the [`wgpu::PresentMode`]: with `Fifo` (the default
+    /// `AutoVsync`) a present can block until a vblank after the compositor tick, so a late frame
+    /// costs two intervals. `Mailbox` or `Immediate` leave pacing to the compositor clock alone,
+    /// DComp still only shows the latest frame presented before each composition, so neither
+    /// tears.
+    ///
+    /// The compositor clock needs Windows 11, on older versions this falls back to
+    /// [`LoopMode::Poll`].
+    pub fn run_vsynced(&mut self) -> Result<()> {
+        self.loop_mode = LoopMode::CompositorClock;
+        self.run()
+    }
+
+    /// Like [`Window::run`], but renders continuously with one frame in flight, waiting before
+    /// each frame until the swapchain accepts it and only then processing input, which reduces
+    /// the time from input to the frame showing it.
+    ///
+    /// The surface is created with a frame latency of 1, two swapchain buffers on DX12, see
+    /// [`SurfaceStateDescriptor::swapchain_buffer_count`]. wgpu creates the swapchain with
+    /// `DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT` and waits on
+    /// `GetFrameLatencyWaitableObject` when a texture is acquired, but keeps the handle, so the
+    /// wait is moved ahead by acquiring the next texture before pumping messages, see
+    /// [`SurfaceState::acquire_ahead`].
+    ///
+    /// With DComp, the object is signaled once the compositor picked up the previous frame, so
+    /// each frame starts right after a composition and shows at the next one, rather than queueing
+    /// behind up to [`SurfaceStateDescriptor::desired_maximum_frame_latency`] frames. Rendering
+    /// must then fit in one composition interval, or frames are skipped.
+    pub fn run_low_latency(&mut self) -> Result<()> {
+        self.surface_descriptor = self
+            .surface_descriptor
+            .clone()
+            .desired_maximum_frame_latency(1);
+        self.loop_mode = LoopMode::LowLatency;
+        self.run()
+    }
+
+    /// Waits until the window's swapchain accepts another frame, if it has one.
+    fn acquire_ahead(&self) {
+        let Some(state) = &self.wgpu_state else {
+            return;
+        };
+        // The paint acquires again and handles the error.
+        if let Err(error) = state.acquire_ahead() {
+            trace!(target: LOG_TARGET, "acquiring ahead failed: {error}");
+        }
+    }
+
+    /// Creates the window, renders and presents a single frame with the render callback, waits
+    /// for the GPU to finish it and optionally writes it to `capture` as PNG, then destroys the
+    /// window. Validates the whole pipeline in automation, e.g. on a CI runner without a GPU,
+    /// where both the D3D11 device and the wgpu adapter fall back to WARP.
+    ///
+    /// Unlike [`Window::run`], which retries failed paints, the first error is returned.
+    pub fn render_one_frame_and_exit(&mut self, capture: Option<&Path>) -> Result<()> {
+        self.create()?;
+        let result = self.render_one_frame(capture);
+
+        self.detach_visual_tree();
+        self.release_device_resources();
+        unsafe {
+            let _ = DestroyWindow(self.hwnd);
+        }
+        result
+    }
+
+    fn render_one_frame(&mut self, capture: Option<&Path>) -> Result<()> {
+        self.paint_handler()?;
+
+        let render = &mut self.render_handler;
+        let Some(state) = &self.wgpu_state else {
+            return Err(Error::D3D(DXGI_ERROR_DEVICE_REMOVED.into()));
+        };
+        state.poll();
+        if let Some(path) = capture {
+            state.capture_png_with(path, |state, view| render(state, view))?;
+            debug!(target: LOG_TARGET, "captured {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Creates the Win32 window. It must not move afterwards, its window procedure refers to it.
+    fn create(&mut self) -> Result<()> {
+        unsafe {
+            let instance = GetModuleHandleA(None)?;
+            // A Unicode class, so `WM_CHAR` carries UTF-16 rather than the ANSI code page.
+            let window_class = w!("window");
+
+            let wc = WNDCLASSW {
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: window_class,
+
+                style: CS_HREDRAW | CS_VREDRAW,
+                lpfnWndProc: Some(Self::wndproc),
+                // No background brush, GDI must not paint anything beneath the DComp content.
+                hbrBackground: HBRUSH::default(),
+                ..Default::default()
+            };
+
+            // The class is shared by every window in the process, so it may already be registered.
+            let atom = RegisterClassW(&wc);
+            if atom == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+                return Err(windows::core::Error::from_win32().into());
+            }
+
+            let monitor = self.monitor.and_then(|index| {
+                let monitor = monitor_work_area(index);
+                if monitor.is_none() {
+                    warn!(target: LOG_TARGET, "no monitor {index}, using the default placement");
+                }
+                monitor
+            });
+
+            let (mut x, mut y) = self.position.unwrap_or((CW_USEDEFAULT, CW_USEDEFAULT));
+            let (mut width, mut height) = match self.size {
+                Some((width, height)) => {
+                    let mut rect = RECT {
+                        left: 0,
+                        top: 0,
+                        right: width as _,
+                        bottom: height as _,
+                    };
+                    match (self.decorations, monitor) {
+                        (false, _) => {}
+                        // The window is created on the monitor, so it gets the monitor's DPI.
+                        (true, Some((_, dpi))) => AdjustWindowRectExForDpi(
+                            &mut rect,
+                            self.style,
+                            false,
+                            WS_EX_NOREDIRECTIONBITMAP,
+                            dpi,
+                        )?,
+                        (true, None) => AdjustWindowRectEx(
+                            &mut rect,
+                            self.style,
+                            false,
+                            WS_EX_NOREDIRECTIONBITMAP,
+                        )?,
+                    }
+                    (rect.right - rect.left, rect.bottom - rect.top)
+                }
+                None => (CW_USEDEFAULT, CW_USEDEFAULT),
+            };
+
+            if let Some((work_area, _)) = monitor {
+                let work_width = work_area.right - work_area.left;
+                let work_height = work_area.bottom - work_area.top;
+                if self.size.is_none() {
+                    (width, height) = (work_width / 2, work_height / 2);
+                }
+                (x, y) = match self.position {
+                    Some((x, y)) => (work_area.left + x, work_area.top + y),
+                    None => (
+                        work_area.left + (work_width - width) / 2,
+                        work_area.top + (work_height - height) / 2,
+                    ),
+                };
+            }
+
+            let title = self.title.clone();
+            // `WS_EX_NOREDIRECTIONBITMAP` is required, the window content comes only from DComp.
+            let mut ex_style = WS_EX_NOREDIRECTIONBITMAP;
+            if self.topmost {
+                ex_style |= WS_EX_TOPMOST;
+            }
+            if self.click_through {
+                ex_style |= WS_EX_TRANSPARENT | WS_EX_LAYERED;
+            }
+            let hwnd = CreateWindowExW(
+                ex_style,
+                window_class,
+                &title,
+                self.style,
+                x,
+                y,
+                width,
+                height,
+                None,
+                None,
+                None,
+                Some(self as *mut _ as _),
+            )?;
+
+            debug_assert!(!hwnd.is_invalid());
+            debug_assert!(hwnd == self.hwnd);
+
+            if self.click_through {
+                SetLayeredWindowAttributes(hwnd, COLORREF(0), 255, LWA_ALPHA)?;
+            }
+
+            if let Some((rgba, width, height)) = &self.icon_rgba {
+                match create_icon(rgba, *width, *height) {
+                    Ok(icon) => {
+                        self.icon = Some(icon);
+                        let icon = LPARAM(icon.0 as _);
+                        SendMessageW(hwnd, WM_SETICON, WPARAM(ICON_SMALL as _), icon);
+                        SendMessageW(hwnd, WM_SETICON, WPARAM(ICON_BIG as _), icon);
+                    }
+                    Err(error) => warn!(target: LOG_TARGET, "failed to create the icon: {error}"),
+                }
+            }
+
+            if let Some(shader) = &self.shader {
+                watch_shader(shader.clone(), hwnd);
+            }
+
+            if let Err(error) = MouseMotion::register() {
+                warn!(target: LOG_TARGET, "failed to register raw mouse input: {error}");
+            }
+
+            Ok(())
+        }
+    }
+
+    extern "system" fn wndproc(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            if message == WM_NCCREATE {
+                let cs = lparam.0 as *const CREATESTRUCTA;
+                let this = (*cs).lpCreateParams as *mut Self;
+                (*this).hwnd = window;
+                (*this).scale_factor = scale_factor(GetDpiForWindow(window));
+
+                SetWindowLongPtrW(window, GWLP_USERDATA, this as _);
+                OPEN_WINDOWS.set(OPEN_WINDOWS.get() + 1);
+            } else {
+                if message == WM_DESTROY {
+                    // Counted here rather than in `message_handler`, dropping a `Window` destroys
+                    // it after detaching it from its window procedure.
+                    OPEN_WINDOWS.set(OPEN_WINDOWS.get() - 1);
+                    if OPEN_WINDOWS.get() == 0 {
+                        PostQuitMessage(0);
+                    }
+                }
+
+                let this = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut Self;
+
+                if !this.is_null() {
+                    return (*this).message_handler(message, wparam, lparam);
+                }
+            }
+
+            DefWindowProcW(window, message, wparam, lparam)
+        }
+    }
+}
+
+thread_local! {
+    /// The windows created on this thread that haven't been destroyed yet.
+    static OPEN_WINDOWS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Creates the Win32 window of each of `windows` and pumps messages for all of them until they
+/// are all destroyed.
+///
+/// The loop polls if any window's [`Window::loop_mode`] is [`LoopMode::Poll`],
+/// [`LoopMode::CompositorClock`] or [`LoopMode::LowLatency`], rendering those windows after every
+/// iteration, and waits for messages otherwise. If any window uses the compositor clock, each
+/// iteration ends by waiting for its next tick, and low latency windows wait for their swapchain
+/// at the start of each iteration. Minimized windows, and inactive ones unless
+/// [`Window::set_pause_when_inactive`] is disabled, are left out.
+///
+/// How polling is paced follows each window's present mode: `Fifo` presents block until a vblank,
+/// while windows presenting with `Mailbox` or `Immediate` are paced to the composition rate, see
+/// [`Window::set_present_pacing`].
+pub fn run_event_loop(windows: &mut [&mut Window]) -> Result<()> {
+    for window in windows.iter_mut() {
+        window.create()?;
+    }
+
+    // Handlers access the windows through their window procedure while the loop runs.
+    let windows: Vec<*mut Window> = windows
+        .iter_mut()
+        .map(|window| &mut **window as _)
+        .collect();
+    let mut message = MSG::default();
+
+    unsafe {
+        loop {
+            let polling = || {
+                windows
+                    .iter()
+                    .filter(|&&window| (*window).renders_continuously())
+            };
+
+            if polling().next().is_none() {
+                if !GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    break;
+                }
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+                continue;
+            }
+
+            for &window in polling() {
+                if (*window).loop_mode == LoopMode::LowLatency {
+                    (*window).acquire_ahead();
+                }
+            }
+
+            while PeekMessageW(&mut message, None, 0, 0, PM_REMOVE).as_bool() {
+                if message.message == WM_QUIT {
+                    return Ok(());
+                }
+                // Posts `WM_CHAR` for key presses that produce text.
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+
+            // Paint synchronously instead of waiting for `WM_PAINT` to be queued.
+            let now = Instant::now();
+            for &window in polling() {
+                if (*window).frame_due(now) {
+                    (*window).request_redraw();
+                    let _ = UpdateWindow((*window).hwnd);
+                }
+            }
+
+            if polling().any(|&window| (*window).loop_mode == LoopMode::CompositorClock) {
+                wait_for_compositor_clock();
+            }
+
+            // Uncapped windows render on every iteration anyway, paced by their presents.
+            if polling().all(|&window| (*window).next_frame.is_some()) {
+                if let Some(next_frame) = polling().filter_map(|&window| (*window).next_frame).min()
+                {
+                    wait_until(next_frame);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Blocks until the next tick of the compositor clock, or returns immediately if it isn't
+/// available.
+///
+/// `DCompositionWaitForCompositorClock` is only exported by dcomp.dll since Windows 11, so it is
+/// looked up at runtime rather than imported, which would keep the binary from loading elsewhere.
+unsafe fn wait_for_compositor_clock() {
+    type WaitForCompositorClock =
+        unsafe extern "system" fn(count: u32, handles: *const HANDLE, timeout: u32) -> u32;
+
+    thread_local! {
+        static WAIT: Option<WaitForCompositorClock> = unsafe {
+            let wait = GetModuleHandleA(s!("dcomp.dll"))
+                .ok()
+                .and_then(|module| GetProcAddress(module, s!("DCompositionWaitForCompositorClock")));
+            if wait.is_none() {
+                warn!(target: LOG_TARGET, "compositor clock unavailable, polling instead");
+            }
+            wait.map(|wait| std::mem::transmute::<_, WaitForCompositorClock>(wait))
+        };
+    }
+
+    if let Some(wait) = WAIT.with(|wait| *wait) {
+        // Bounded, so a compositor that stops ticking doesn't stall the message loop.
+        if wait(0, std::ptr::null(), 100) == WAIT_FAILED.0 {
+            trace!(target: LOG_TARGET, "waiting for the compositor clock failed");
+        }
+    }
+}
+
+/// Blocks until `deadline` or until a message arrives, on a high-resolution waitable timer
+/// rather than the scheduler's default 15.6 ms granularity where available.
+unsafe fn wait_until(deadline: Instant) {
+    thread_local! {
+        // Kept for the lifetime of the thread.
+        static TIMER: Option<HANDLE> = unsafe {
+            CreateWaitableTimerExW(
+                None,
+                None,
+                CREATE_WAITABLE_TIMER_HIGH_RESOLUTION,
+                TIMER_ALL_ACCESS.0,
+            )
+            // High-resolution timers need Windows 10 1803.
+            .or_else(|_| CreateWaitableTimerExW(None, None, 0, TIMER_ALL_ACCESS.0))
+            .inspect_err(|error| warn!(target: LOG_TARGET, "failed to create a waitable timer: {error}"))
+            .ok()
+        };
+    }
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return;
+    }
+    if let Some(timer) = TIMER.with(|timer| *timer) {
+        // Negative due times are relative, in 100 ns units.
+        let due_time = -((remaining.as_nanos() / 100) as i64).max(1);
+        if SetWaitableTimer(timer, &due_time, 0, None, None, false).is_ok() {
+            MsgWaitForMultipleObjects(Some(&[timer]), false, INFINITE, QS_ALLINPUT);
+            return;
+        }
+    }
+    MsgWaitForMultipleObjects(None, false, remaining.as_millis() as u32, QS_ALLINPUT);
+}
+
+impl Drop for Window {
+    /// Releases the device resources in the order described by `release_device_resources`, then
+    /// destroys the window if it still exists, so its window procedure no longer refers to `self`.
+    fn drop(&mut self) {
+        self.detach_visual_tree();
+        self.release_device_resources();
+
+        unsafe {
+            if IsWindow(self.hwnd).as_bool() {
+                debug!(target: LOG_TARGET, "destroy window");
+                SetWindowLongPtrW(self.hwnd, GWLP_USERDATA, 0);
+                let _ = DestroyWindow(self.hwnd);
+            }
+        }
+        self.destroy_icon();
+    }
+}
+
+/// Creates a surface for `visual`, rendering with the devices of `shared` if given or requesting
+/// its own otherwise.
+///
+/// # Safety
+///
+/// `visual` must be a valid `IDCompositionVisual` pointer that outlives the returned state.
+pub(crate) unsafe fn new_surface_state(
+    instance: &wgpu::Instance,
+    shared: Option<&SharedDevice>,
+    visual: *mut c_void,
+    width: u32,
+    height: u32,
+    descriptor: &SurfaceStateDescriptor,
+) -> Result<SurfaceState> {
+    let state = match shared {
+        Some(shared) => pollster::block_on(SurfaceState::new_with_shared_device(
+            visual, width, height, descriptor, shared,
+        )),
+        None => pollster::block_on(SurfaceState::new(
+            instance, visual, width, height, descriptor,
+        )),
+    };
+    Ok(state?)
+}
+
+/// Renders a frame of `state`, whose surface targets `visual`, with `render`. If the surface is
+/// still lost after reconfiguring, as seen on some drivers after rapid resizes, it is recreated
+/// and the frame rendered again, a new swapchain being cheaper than rebuilding all device
+/// resources.
+///
+/// # Safety
+///
+/// `state` must have been created from `visual`.
+unsafe fn render_recreating_lost(
+    state: &mut SurfaceState,
+    instance: &wgpu::Instance,
+    visual: &IDCompositionVisual2,
+    mut render: impl FnMut(&SurfaceState, &wgpu::TextureView),
+) -> Result<()> {
+    match state.render_with(&mut render) {
+        Err(Error::Frame(wgpu::SurfaceError::Lost)) => {
+            warn!(target: LOG_TARGET, "surface still lost, recreating it");
+            state.recreate_surface(instance, visual.as_raw())?;
+            state.render_with(render)
+        }
+        result => result,
+    }
+}
+
+pub(crate) fn close_on_escape(window: &mut Window, event: KeyEvent) {
+    if event.pressed && event.key == VK_ESCAPE {
+        window.close();
+    }
+}
+
+/// The work area and DPI of the monitor at `index` in `EnumDisplayMonitors` order.
+fn monitor_work_area(index: usize) -> Option<(RECT, u32)> {
+    unsafe extern "system" fn push(
+        monitor: HMONITOR,
+        _: HDC,
+        _: *mut RECT,
+        monitors: LPARAM,
+    ) -> BOOL {
+        (*(monitors.0 as *mut Vec<HMONITOR>)).push(monitor);
+        TRUE
+    }
+
+    let mut monitors: Vec<HMONITOR> = Vec::new();
+    unsafe {
+        EnumDisplayMonitors(None, None, Some(push), LPARAM(&mut monitors as *mut _ as _))
+            .ok()
+            .ok()?;
+
+        let monitor = *monitors.get(index)?;
+        let mut info = MONITORINFO {
+            cbSize: size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        GetMonitorInfoW(monitor, &mut info).ok().ok()?;
+        let (mut dpi, mut dpi_y) = (USER_DEFAULT_SCREEN_DPI, USER_DEFAULT_SCREEN_DPI);
+        GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi, &mut dpi_y).ok()?;
+        Some((info.rcWork, dpi))
+    }
+}
+
+/// Creates an icon from `width` x `height` RGBA pixels, which must hold exactly that many.
+fn create_icon(rgba: &[u8], width: u32, height: u32) -> Result<HICON> {
+    // 32 bpp bitmaps are BGRA, and their alpha makes the mask unused, but it must still exist.
+    let bgra: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+        .collect();
+    let mask = vec![0u8; (width.div_ceil(16) * 2 * height) as usize];
+
+    unsafe {
+        let color = CreateBitmap(width as i32, height as i32, 1, 32, Some(bgra.as_ptr() as _));
+        let mask = CreateBitmap(width as i32, height as i32, 1, 1, Some(mask.as_ptr() as _));
+        let icon = if color.is_invalid() || mask.is_invalid() {
+            Err(windows::core::Error::from_win32())
+        } else {
+            CreateIconIndirect(&ICONINFO {
+                fIcon: TRUE,
+                xHotspot: 0,
+                yHotspot: 0,
+                hbmMask: mask,
+                hbmColor: color,
+            })
+        };
+        // The icon keeps its own copies of the bitmaps.
+        let _ = DeleteObject(color);
+        let _ = DeleteObject(mask);
+        Ok(icon?)
+    }
+}
+
+fn client_size(hwnd: HWND) -> Result<(u32, u32)> {
+    let mut rect = RECT::default();
+    unsafe { GetClientRect(hwnd, &mut rect)? };
+    Ok(((rect.right - rect.left) as _, (rect.bottom - rect.top) as _))
+}
+
+fn scale_factor(dpi: u32) -> f64 {
+    dpi as f64 / USER_DEFAULT_SCREEN_DPI as f64
+}
+
+/// Creates a target for `hwnd` with a root visual holding the visual wgpu renders to, and returns
+/// all three.
+unsafe fn create_visual_tree(
+    desktop: &IDCompositionDesktopDevice,
+    hwnd: HWND,
+) -> windows::core::Result<(
+    IDCompositionTarget,
+    IDCompositionVisual2,
+    IDCompositionVisual2,
+)> {
+    let target = create_target(desktop, hwnd)?;
+
+    let root_visual = desktop.CreateVisual()?;
+    target.SetRoot(&root_visual)?;
+
+    let wgpu_visual = desktop.CreateVisual()?;
+    root_visual.AddVisual(&wgpu_visual, false, None)?;
+
+    Ok((target, root_visual, wgpu_visual))
+}
+
+/// How many times [`create_target`] tries to create the target of an occupied window.
+const TARGET_ATTEMPTS: u32 = 3;
+
+/// How long [`create_target`] waits between attempts.
+const TARGET_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Creates the composition target of `hwnd`, for content topmost over any child windows.
+///
+/// Callers release the window's previous target first, but the previous tree can still occupy
+/// the window for a moment, e.g. until DWM processes the release of the old device's last commit
+/// after device loss. `CreateTargetForHwnd` then fails with
+/// `DCOMPOSITION_ERROR_WINDOW_ALREADY_COMPOSED`, so it is retried a few times before giving up
+/// with an error naming the likely causes.
+unsafe fn create_target(
+    desktop: &IDCompositionDesktopDevice,
+    hwnd: HWND,
+) -> windows::core::Result<IDCompositionTarget> {
+    let mut attempt = 1;
+    loop {
+        match desktop.CreateTargetForHwnd(hwnd, true) {
+            Err(error) if error.code() == DCOMPOSITION_ERROR_WINDOW_ALREADY_COMPOSED => {
+                if attempt == TARGET_ATTEMPTS {
+                    return Err(windows::core::Error::new(
+                        error.code(),
+                        "the window already has a composition target: a target created for it \
+                         elsewhere in the process is still alive, or it is presented by a \
+                         swapchain created with CreateSwapChainForHwnd",
+                    ));
+                }
+                warn!(
+                    target: LOG_TARGET,
+                    "window already composed, retrying in {TARGET_RETRY_DELAY:?}"
+                );
+                attempt += 1;
+                std::thread::sleep(TARGET_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Toggles the styles making `hwnd` click-through, see [`Window::set_click_through`].
+unsafe fn apply_click_through(hwnd: HWND, click_through: bool) -> windows::core::Result<()> {
+    let ex_style = WINDOW_EX_STYLE(GetWindowLongPtrW(hwnd, GWL_EXSTYLE) as u32);
+    let ex_style = if click_through {
+        ex_style | WS_EX_TRANSPARENT | WS_EX_LAYERED
+    } else {
+        ex_style & !(WS_EX_TRANSPARENT | WS_EX_LAYERED)
+    };
+    SetWindowLongPtrW(hwnd, GWL_EXSTYLE, ex_style.0 as _);
+    if click_through {
+        SetLayeredWindowAttributes(hwnd, COLORREF(0), 255, LWA_ALPHA)?;
+    }
+    Ok(())
+}
+
+/// Moves the children of `root` in `layers` right above `wgpu_visual`, each one above the one
+/// before it.
+unsafe fn restack_layers<'a>(
+    root: &IDCompositionVisual2,
+    wgpu_visual: &IDCompositionVisual2,
+    layers: impl IntoIterator<Item = &'a IDCompositionVisual2>,
+) -> windows::core::Result<()> {
+    let mut below = wgpu_visual;
+    for visual in layers {
+        root.RemoveVisual(visual)?;
+        root.AddVisual(visual, true, below)?;
+        below = visual;
+    }
+    Ok(())
+}
+
+/// Sets `transform` on `visual` through a matrix transform object created from `desktop`, sampling
+/// the visual's content with linear filtering.
+unsafe fn apply_transform(
+    desktop: &IDCompositionDesktopDevice,
+    visual: &IDCompositionVisual2,
+    transform: &Matrix3x2,
+) -> windows::core::Result<()> {
+    // The default nearest neighbor sampling makes scaled content blocky.
+    visual.SetBitmapInterpolationMode(DCOMPOSITION_BITMAP_INTERPOLATION_MODE_LINEAR)?;
+    let matrix = desktop.CreateMatrixTransform()?;
+    matrix.SetMatrix(transform)?;
+    visual.SetTransform(&matrix)
+}
+
+/// Sets the 3D `transform` on `visual`, which must implement `IDCompositionVisual3`.
+unsafe fn apply_transform_3d(
+    visual: &IDCompositionVisual2,
+    transform: &Matrix4x4,
+) -> windows::core::Result<()> {
+    let visual: IDCompositionVisual3 = visual.cast()?;
+    visual.SetBitmapInterpolationMode(DCOMPOSITION_BITMAP_INTERPOLATION_MODE_LINEAR)?;
+    let Matrix4x4 {
+        M11,
+        M12,
+        M13,
+        M14,
+        M21,
+        M22,
+        M23,
+        M24,
+        M31,
+        M32,
+        M33,
+        M34,
+        M41,
+        M42,
+        M43,
+        M44,
+    } = *transform;
+    let matrix = D2D_MATRIX_4X4_F {
+        Anonymous: D2D_MATRIX_4X4_F_0 {
+            m: [
+                M11, M12, M13, M14, M21, M22, M23, M24, M31, M32, M33, M34, M41, M42, M43, M44,
+            ],
+        },
+    };
+    visual.SetTransform2(&matrix)
+}
+
+/// Sets the backface visibility and depth mode of `visual`, which must implement
+/// `IDCompositionVisual3`.
+unsafe fn apply_visual_3d(
+    visual: &IDCompositionVisual2,
+    backface_visible: bool,
+    depth_mode: DCOMPOSITION_DEPTH_MODE,
+) -> windows::core::Result<()> {
+    let visual: IDCompositionVisual3 = visual.cast()?;
+    visual.SetBackFaceVisibility(if backface_visible {
+        DCOMPOSITION_BACKFACE_VISIBILITY_VISIBLE
+    } else {
+        DCOMPOSITION_BACKFACE_VISIBILITY_HIDDEN
+    })?;
+    visual.SetDepthMode(depth_mode)
+}
+
+/// `transform` as a 3D transform in the z = 0 plane.
+fn matrix_4x4(transform: &Matrix3x2) -> Matrix4x4 {
+    Matrix4x4 {
+        M11: transform.M11,
+        M12: transform.M12,
+        M21: transform.M21,
+        M22: transform.M22,
+        M33: 1.0,
+        M41: transform.M31,
+        M42: transform.M32,
+        M44: 1.0,
+        ..Default::default()
+    }
+}
+
+/// Sets a rectangle clip created from `desktop` with corners rounded by `radius` on `visual`, or
+/// removes its clip if `clip` is `None`.
+unsafe fn apply_clip(
+    desktop: &IDCompositionDesktopDevice,
+    visual: &IDCompositionVisual2,
+    clip: Option<&D2D_RECT_F>,
+    radius: f32,
+) -> windows::core::Result<()> {
+    let Some(clip) = clip else {
+        return visual.SetClip(None);
+    };
+
+    let rectangle = desktop.CreateRectangleClip()?;
+    rectangle.SetLeft2(clip.left)?;
+    rectangle.SetTop2(clip.top)?;
+    rectangle.SetRight2(clip.right)?;
+    rectangle.SetBottom2(clip.bottom)?;
+
+    // Radii over half the rectangle would make the corners overlap.
+    let radius_x = radius.clamp(0.0, (clip.right - clip.left).max(0.0) / 2.0);
+    let radius_y = radius.clamp(0.0, (clip.bottom - clip.top).max(0.0) / 2.0);
+    if radius_x > 0.0 && radius_y > 0.0 {
+        rectangle.SetTopLeftRadiusX2(radius_x)?;
+        rectangle.SetTopLeftRadiusY2(radius_y)?;
+        rectangle.SetTopRightRadiusX2(radius_x)?;
+        rectangle.SetTopRightRadiusY2(radius_y)?;
+        rectangle.SetBottomLeftRadiusX2(radius_x)?;
+        rectangle.SetBottomLeftRadiusY2(radius_y)?;
+        rectangle.SetBottomRightRadiusX2(radius_x)?;
+        rectangle.SetBottomRightRadiusY2(radius_y)?;
+    }
+    visual.SetClip(&rectangle)
+}
+
+/// Creates a visual blurred with a standard deviation of `std_dev` and adds it to `root` beneath
+/// `wgpu_visual`.
+unsafe fn create_backdrop(
+    desktop: &IDCompositionDesktopDevice,
+    root: &IDCompositionVisual2,
+    wgpu_visual: &IDCompositionVisual2,
+    std_dev: f32,
+) -> windows::core::Result<IDCompositionVisual2> {
+    let backdrop = desktop.CreateVisual()?;
+    apply_blur(desktop, &backdrop, std_dev)?;
+    root.AddVisual(&backdrop, false, wgpu_visual)?;
+    Ok(backdrop)
+}
+
+/// Sets a Gaussian blur effect with a standard deviation of `std_dev` on `visual`.
+unsafe fn apply_blur(
+    desktop: &IDCompositionDesktopDevice,
+    visual: &IDCompositionVisual2,
+    std_dev: f32,
+) -> windows::core::Result<()> {
+    // Filter effects are only created by `IDCompositionDevice3`.
+    let device: IDCompositionDevice3 = desktop.cast()?;
+    let blur = device.CreateGaussianBlurEffect()?;
+    blur.SetStandardDeviation2(std_dev)?;
+    visual.SetEffect(&blur)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SurfaceStateError, WindowBuilder};
+
+    #[test]
+    fn surface_uses_size_of_wm_size_before_creation() {
+        let mut window = WindowBuilder::new().size(200, 150).build().unwrap();
+        // Kept hidden, so running the tests doesn't pop up a window.
+        window.style &= !WS_VISIBLE;
+        window.create().unwrap();
+        // No message loop dispatches a `WM_PAINT`, so nothing has created the surface yet.
+        assert!(window.wgpu_state.is_none());
+
+        // Differs from the client area `GetClientRect` would report.
+        let size = LPARAM(((80 << 16) | 120) as isize);
+        unsafe { SendMessageW(window.hwnd, WM_SIZE, WPARAM(SIZE_RESTORED as usize), size) };
+        match window.create_device_resources() {
+            Err(Error::Surface(SurfaceStateError::NoAdapter)) => {
+                eprintln!("no adapter available, skipping");
+                return;
+            }
+            result => result.unwrap(),
+        }
+
+        let config = &window.wgpu_state.as_ref().unwrap().surface_config;
+        assert_eq!((config.width, config.height), (120, 80));
+    }
+}
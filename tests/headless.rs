@@ -0,0 +1,205 @@
+use std::{env, fs, fs::File, path::Path};
+
+use wgpu::{CompositeAlphaMode, TextureFormat};
+use wgpu_test::{Error, HeadlessSurface, SurfaceState, SurfaceStateDescriptor, SurfaceStateError};
+
+/// Where the reference images of [`assert_snapshot`] are stored, named after their snapshot.
+const SNAPSHOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots");
+
+/// Set to make [`assert_snapshot`] overwrite the reference images instead of comparing to them.
+const UPDATE_SNAPSHOTS: &str = "UPDATE_SNAPSHOTS";
+
+/// Creates a DX12 surface, or returns `None` on machines without any adapter.
+fn headless_surface(descriptor: &SurfaceStateDescriptor) -> Option<HeadlessSurface> {
+    match HeadlessSurface::new(wgpu::Backends::DX12, 64, 64, descriptor) {
+        Err(Error::Surface(SurfaceStateError::NoAdapter)) => {
+            eprintln!("no adapter available, skipping");
+            None
+        }
+        result => Some(result.unwrap()),
+    }
+}
+
+/// Captures a frame of `state` rendered by `render` and compares it to the reference image
+/// `name`.png, failing if any channel of any pixel differs by more than `tolerance`.
+///
+/// The reference is written instead if [`UPDATE_SNAPSHOTS`] is set. A missing reference fails, so
+/// deleting one can't disable the check. On failure the captured frame is kept in the target
+/// directory for inspection.
+fn assert_snapshot(
+    state: &SurfaceState,
+    name: &str,
+    tolerance: u8,
+    render: impl FnOnce(&SurfaceState, &wgpu::TextureView),
+) {
+    let reference = Path::new(SNAPSHOT_DIR).join(format!("{name}.png"));
+    let actual = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.png"));
+    state.capture_png_with(&actual, render).unwrap();
+
+    if env::var_os(UPDATE_SNAPSHOTS).is_some() {
+        fs::create_dir_all(SNAPSHOT_DIR).unwrap();
+        fs::copy(&actual, &reference).unwrap();
+        eprintln!("wrote snapshot {}", reference.display());
+        return;
+    }
+    assert!(
+        reference.exists(),
+        "snapshot {name} has no reference {}, captured to {}; set {UPDATE_SNAPSHOTS} to create it",
+        reference.display(),
+        actual.display()
+    );
+
+    let (expected_size, expected) = read_png(&reference);
+    let (actual_size, pixels) = read_png(&actual);
+    assert_eq!(
+        actual_size,
+        expected_size,
+        "snapshot {name} has a different size, captured to {}",
+        actual.display()
+    );
+
+    let mismatch = pixels
+        .chunks_exact(4)
+        .zip(expected.chunks_exact(4))
+        .position(|(pixel, expected)| {
+            pixel
+                .iter()
+                .zip(expected)
+                .any(|(channel, expected)| channel.abs_diff(*expected) > tolerance)
+        });
+    if let Some(index) = mismatch {
+        let (x, y) = (index as u32 % actual_size.0, index as u32 / actual_size.0);
+        let pixel = &pixels[index * 4..][..4];
+        let expected = &expected[index * 4..][..4];
+        panic!(
+            "snapshot {name} differs at {x}, {y}: {pixel:?} instead of {expected:?} \
+             (tolerance {tolerance}), captured to {}; set {UPDATE_SNAPSHOTS} to accept it",
+            actual.display()
+        );
+    }
+}
+
+/// Decodes the PNG at `path` into its size and RGBA pixels.
+fn read_png(path: &Path) -> ((u32, u32), Vec<u8>) {
+    let mut reader = png::Decoder::new(File::open(path).unwrap())
+        .read_info()
+        .unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} isn't 8-bit RGBA",
+        path.display()
+    );
+    pixels.truncate(info.buffer_size());
+    ((info.width, info.height), pixels)
+}
+
+#[test]
+fn selects_first_supported_format_and_srgb_view() {
+    let descriptor = SurfaceStateDescriptor::new().formats([
+        TextureFormat::Rgba32Float,
+        TextureFormat::Bgra8Unorm,
+        TextureFormat::Bgra8UnormSrgb,
+    ]);
+    let Some(surface) = headless_surface(&descriptor) else {
+        return;
+    };
+
+    assert_eq!(surface.state.format, TextureFormat::Bgra8Unorm);
+    assert_eq!(surface.state.view_format, TextureFormat::Bgra8UnormSrgb);
+    assert_eq!(
+        surface.state.surface_config.format,
+        TextureFormat::Bgra8Unorm
+    );
+}
+
+#[test]
+fn selects_premultiplied_alpha_by_default() {
+    let Some(surface) = headless_surface(&SurfaceStateDescriptor::default()) else {
+        return;
+    };
+
+    assert_eq!(
+        surface.state.surface_config.alpha_mode,
+        CompositeAlphaMode::PreMultiplied
+    );
+}
+
+#[test]
+fn renders_after_resize() {
+    let Some(mut surface) = headless_surface(&SurfaceStateDescriptor::default()) else {
+        return;
+    };
+
+    // DComp surfaces have no window size to fall out of date with, so the closest to an outdated
+    // surface is a frame right after reconfiguring.
+    surface.state.render().unwrap();
+    surface.state.resize(32, 16);
+    surface.state.render().unwrap();
+    assert_eq!(
+        (
+            surface.state.surface_config.width,
+            surface.state.surface_config.height
+        ),
+        (32, 16)
+    );
+}
+
+#[test]
+fn clear_matches_snapshot() {
+    let Some(surface) = headless_surface(&SurfaceStateDescriptor::default()) else {
+        return;
+    };
+
+    // The default semi-transparent red, through format selection, the sRGB view and the alpha
+    // written as is.
+    assert!(!surface.state.draw_triangle);
+    assert_snapshot(&surface.state, "clear", 1, |state, view| state.draw(view));
+}
+
+#[test]
+fn msaa_resolves_edges_in_linear_space() {
+    let descriptor = SurfaceStateDescriptor::new().sample_count(4);
+    let Some(mut surface) = headless_surface(&descriptor) else {
+        return;
+    };
+    assert_eq!(surface.state.sample_count(), 4);
+
+    // An opaque blue triangle over opaque red, so every pixel covers both in some proportion.
+    surface.state.set_clear_color(wgpu::Color::RED);
+    surface.state.draw_triangle = true;
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("msaa.png");
+    surface
+        .state
+        .capture_png_with(&path, |state, view| state.draw(view))
+        .unwrap();
+    let (_, pixels) = read_png(&path);
+
+    // Averaged in linear space, the proportions add up to 1 once decoded from sRGB. Averaging the
+    // encoded values would leave edges darker, around 0.43 for an even split.
+    let linear = |value: u8| {
+        let value = value as f64 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let mut edges = 0;
+    for (index, pixel) in pixels.chunks_exact(4).enumerate() {
+        let coverage = linear(pixel[0]) + linear(pixel[2]);
+        assert!(
+            (coverage - 1.0).abs() < 0.05,
+            "pixel {index} is {pixel:?}, {coverage} in linear space"
+        );
+        if pixel[0] > 16 && pixel[2] > 16 {
+            edges += 1;
+        }
+    }
+    assert!(
+        edges > 0,
+        "no edge pixel mixes the triangle and the clear color"
+    );
+}